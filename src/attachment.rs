@@ -0,0 +1,176 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use reqwest::header::CONTENT_TYPE;
+
+use crate::i18n::tr;
+
+/// A file attached to the prompt with `--file`, converted to text, or an image
+#[derive(Clone)]
+pub struct Attachment {
+    /// Path or URL the attachment was loaded from
+    pub name: String,
+    /// Language of the Markdown code block holding the content, e.g. `csv`
+    pub lang: String,
+    /// The text, or the base64-encoded content of an image
+    pub content: String,
+    /// MIME type of an image, sent as such to the model rather than in the prompt
+    pub image: Option<String>,
+    /// Index of the message the attachment was sent with, once sent
+    pub message: Option<usize>,
+}
+
+impl Attachment {
+    /// Load a local file or download a URL, and convert it to text according to its type
+    pub async fn load(source: &str) -> Result<Self> {
+        let (bytes, content_type) =
+            if source.starts_with("http://") || source.starts_with("https://") {
+                let response = reqwest::get(source)
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .with_context(|| format!("Failed to download {source}"))?;
+                let content_type = response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.split(';').next().unwrap().trim().to_lowercase());
+                (response.bytes().await?.to_vec(), content_type)
+            } else {
+                let bytes =
+                    std::fs::read(source).with_context(|| format!("Failed to read {source}"))?;
+                (bytes, None)
+            };
+
+        // Servers often send a generic type for downloads, so the extension is a better hint
+        let path = source.split(['?', '#']).next().unwrap();
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase)
+            .unwrap_or_default();
+        let kind = match content_type.as_deref() {
+            Some("application/octet-stream") | None => Kind::detect(&extension, &bytes),
+            Some(mime) => Kind::from_mime(mime).unwrap_or_else(|| Kind::detect(&extension, &bytes)),
+        };
+
+        let name = source.to_owned();
+        let mut image = None;
+        let (lang, content) = match kind {
+            Kind::Text => (extension, text(&name, bytes)?),
+            Kind::Csv => ("csv".to_owned(), text(&name, bytes)?),
+            Kind::Pdf => (String::new(), pdf_to_text(&name, &bytes)?),
+            Kind::Image => {
+                let mime = image_mime(&extension, &bytes)
+                    .with_context(|| (tr().image_attachment_unsupported)(&name))?;
+                image = Some(mime.to_owned());
+                let data = base64::engine::general_purpose::STANDARD.encode(&bytes);
+                (String::new(), data)
+            }
+        };
+        Ok(Self {
+            name,
+            lang,
+            content,
+            image,
+            message: None,
+        })
+    }
+}
+
+/// How an attachment is converted to text
+#[derive(Debug, PartialEq)]
+enum Kind {
+    Text,
+    Csv,
+    Pdf,
+    Image,
+}
+
+impl Kind {
+    fn from_mime(mime: &str) -> Option<Self> {
+        match mime {
+            "text/csv" => Some(Self::Csv),
+            "application/pdf" => Some(Self::Pdf),
+            m if m.starts_with("image/") => Some(Self::Image),
+            m if m.starts_with("text/")
+                || m.ends_with("json")
+                || m.ends_with("xml")
+                || m.ends_with("yaml")
+                || m.ends_with("toml")
+                || m == "application/javascript" =>
+            {
+                Some(Self::Text)
+            }
+            _ => None,
+        }
+    }
+
+    /// Guess the kind from the extension and the leading bytes of the content
+    fn detect(extension: &str, bytes: &[u8]) -> Self {
+        match extension {
+            "csv" | "tsv" => Self::Csv,
+            "pdf" => Self::Pdf,
+            "png" | "jpg" | "jpeg" | "gif" | "webp" => Self::Image,
+            _ if bytes.starts_with(b"%PDF") => Self::Pdf,
+            _ if bytes.starts_with(b"\x89PNG")
+                || bytes.starts_with(b"\xff\xd8\xff")
+                || bytes.starts_with(b"GIF8")
+                || (bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP")) =>
+            {
+                Self::Image
+            }
+            _ => Self::Text,
+        }
+    }
+}
+
+/// MIME type of an image in one of the formats accepted by the APIs, from its leading bytes
+/// or else its extension
+fn image_mime(extension: &str, bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF8") {
+        Some("image/gif")
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else {
+        match extension {
+            "png" => Some("image/png"),
+            "jpg" | "jpeg" => Some("image/jpeg"),
+            "gif" => Some("image/gif"),
+            "webp" => Some("image/webp"),
+            _ => None,
+        }
+    }
+}
+
+fn text(name: &str, bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).with_context(|| format!("{name} is neither text nor a known format"))
+}
+
+/// Extract the text of a PDF with `pdftotext` from poppler
+fn pdf_to_text(name: &str, bytes: &[u8]) -> Result<String> {
+    let mut child = Command::new("pdftotext")
+        .args(["-layout", "-", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Attaching {name} requires `pdftotext` (from poppler-utils)"))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let input = bytes.to_vec();
+    let writer = std::thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+    if !output.status.success() {
+        bail!("Failed to extract the text of {name}");
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,144 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::model::{Message, MessageMeta};
+use crate::storage::Lock;
+
+/// Hash linking the first entry of a log
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// An entry of the audit log: one request and its response.
+///
+/// Entries are stored as JSON lines. `hash` is the SHA-256 of the entry without `hash`, with
+/// the keys sorted, and `prev` is the hash of the previous entry. Editing, inserting or
+/// removing an entry breaks the chain.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entry {
+    /// Position in the log, starting at 1
+    pub seq: u64,
+    /// Seconds since UNIX epoch
+    pub time: u64,
+    pub request: Vec<Message>,
+    pub response: Message,
+    /// Model, parameters and provider of the response
+    pub meta: Option<MessageMeta>,
+    pub prev: String,
+    pub hash: String,
+}
+
+/// Path of the audit log, with a leading `~/` expanded
+pub fn log_path(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Append a request and its response to the audit log, chained to the last entry
+pub fn append(path: &Path, request: Vec<Message>, response: &Message) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open the audit log {}", path.display()))?;
+    // Other instances appending meanwhile would fork the chain
+    let _lock = Lock::new(&file).context("Failed to lock the audit log")?;
+
+    let (seq, prev) = match last_line(&mut file)? {
+        Some(line) => {
+            let last: Entry = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid last entry in {}", path.display()))?;
+            (last.seq + 1, last.hash)
+        }
+        None => (1, GENESIS.to_owned()),
+    };
+    let mut entry = serde_json::to_value(Entry {
+        seq,
+        time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        request,
+        response: response.clone(),
+        meta: response.meta.clone(),
+        prev,
+        hash: String::new(),
+    })?;
+    let hash = hash(&mut entry);
+    entry["hash"] = Value::String(hash);
+    writeln!(file, "{entry}")?;
+    Ok(())
+}
+
+/// Check the hash chain of the audit log, returning the number of entries
+pub fn verify(path: &Path) -> Result<u64> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open the audit log {}", path.display()))?;
+    let mut prev = GENESIS.to_owned();
+    let mut count = 0;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let n = i + 1;
+        let mut value: Value =
+            serde_json::from_str(&line).with_context(|| format!("Line {n} isn't a valid entry"))?;
+        let entry: Entry = serde_json::from_value(value.clone())
+            .with_context(|| format!("Line {n} isn't a valid entry"))?;
+        if entry.seq != count + 1 {
+            bail!(
+                "Line {n} has sequence number {}, expected {}",
+                entry.seq,
+                count + 1
+            );
+        }
+        if entry.prev != prev {
+            bail!("Line {n} doesn't follow the previous entry: entries were removed or inserted");
+        }
+        if hash(&mut value) != entry.hash {
+            bail!("Line {n} was modified after it was logged");
+        }
+        prev = entry.hash;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// SHA-256 of the entry without its `hash`, which is removed
+fn hash(entry: &mut Value) -> String {
+    if let Some(entry) = entry.as_object_mut() {
+        entry.remove("hash");
+    }
+    // Objects are serialized with sorted keys
+    let digest = Sha256::digest(entry.to_string().as_bytes());
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The last line of the file, read backwards from the end
+fn last_line(file: &mut File) -> Result<Option<String>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut start = len;
+    let mut tail = Vec::new();
+    while start > 0 {
+        let chunk = start.min(4096);
+        start -= chunk;
+        let mut buf = vec![0; chunk as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buf)?;
+        buf.extend_from_slice(&tail);
+        tail = buf;
+        // Skip the newline ending the last line
+        let body = tail.strip_suffix(b"\n").unwrap_or(&tail);
+        if let Some(i) = body.iter().rposition(|&b| b == b'\n') {
+            return Ok(Some(String::from_utf8_lossy(&body[i + 1..]).into_owned()));
+        }
+    }
+    let body = tail.strip_suffix(b"\n").unwrap_or(&tail);
+    Ok((!body.is_empty()).then(|| String::from_utf8_lossy(body).into_owned()))
+}
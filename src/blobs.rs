@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::storage::{atomic_write, data_dir};
+
+/// Age under which an unreferenced blob, or a temporary file, is kept: another instance may
+/// have stored it for a session it's about to save
+const MIN_UNREFERENCED_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Store content by its SHA-256 hash, so that the same content is stored only once.
+/// Returns the hash.
+pub fn store(content: &str) -> Result<String> {
+    let hash = format!("{:x}", Sha256::digest(content.as_bytes()));
+    let path = path(&hash)?;
+    // A blob stored again is touched, so that a concurrent prune keeps it
+    let touched = std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if touched.is_err() {
+        atomic_write(&path, content)?;
+    }
+    Ok(hash)
+}
+
+pub fn load(hash: &str) -> Result<String> {
+    std::fs::read_to_string(path(hash)?).with_context(|| format!("Missing blob {hash}"))
+}
+
+/// Size of a blob in bytes, 0 if it's missing
+pub fn size(hash: &str) -> u64 {
+    path(hash)
+        .and_then(|path| Ok(std::fs::metadata(path)?))
+        .map_or(0, |m| m.len())
+}
+
+/// Remove the blobs which aren't referenced anymore, unless they were stored recently
+pub fn remove_unreferenced(referenced: &HashSet<&str>) -> Result<()> {
+    let dir = data_dir().join("blobs");
+    let Ok(prefixes) = std::fs::read_dir(&dir) else {
+        return Ok(());
+    };
+    for prefix in prefixes {
+        let prefix = prefix?;
+        if !prefix.file_type()?.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(prefix.path())? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let hash = format!("{}{name}", prefix.file_name().to_string_lossy());
+            if referenced.contains(hash.as_str()) {
+                continue;
+            }
+            let age = entry
+                .metadata()?
+                .modified()
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+            if age.is_some_and(|age| age >= MIN_UNREFERENCED_AGE) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Blobs are spread over subdirectories by the first byte of the hash, like git objects.
+/// The hash comes from session files, so it's checked to stay in the blob store.
+fn path(hash: &str) -> Result<PathBuf> {
+    if hash.len() != 64 || !hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        bail!("Invalid blob hash {hash:?}");
+    }
+    Ok(data_dir().join("blobs").join(&hash[..2]).join(&hash[2..]))
+}
@@ -0,0 +1,127 @@
+/// Keeps only the contents of fenced code blocks in streamed text, for `--code-only`.
+///
+/// Prose is dropped as it arrives. Lines inside a block are printed as soon as they can't be
+/// the closing fence, so that the code streams live; the fences themselves are dropped.
+/// Consecutive blocks are separated by a blank line.
+#[derive(Default)]
+pub struct CodeFilter {
+    /// The current line, up to the last chunk
+    line: String,
+    /// Whether the current line was found not to be a closing fence, and is passed through
+    passing: bool,
+    /// Fence character, length and indentation of the open block
+    fence: Option<(char, usize, usize)>,
+    /// Number of blocks opened so far
+    blocks: usize,
+    /// Whether any text was pushed since the last `finish`
+    seen: bool,
+}
+
+impl CodeFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk of text, returning the code to print now
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.seen |= !chunk.is_empty();
+        let mut out = String::new();
+        for part in chunk.split_inclusive('\n') {
+            let complete = part.ends_with('\n');
+            if self.passing {
+                out.push_str(part);
+            } else {
+                self.line.push_str(part);
+                if let Some((c, len, indent)) = self.fence {
+                    if !complete && could_close(&self.line, c, len) {
+                        continue;
+                    }
+                    if !(complete && is_closing(&self.line, c, len)) {
+                        self.passing = true;
+                        out.push_str(strip_indent(&self.line, indent));
+                    }
+                } else if complete {
+                    if let Some(fence) = opening(&self.line) {
+                        if self.blocks > 0 {
+                            out.push('\n');
+                        }
+                        self.blocks += 1;
+                        self.fence = Some(fence);
+                        self.line.clear();
+                        continue;
+                    }
+                }
+            }
+            if complete {
+                if self.fence.is_some() && !self.passing {
+                    // The closing fence
+                    self.fence = None;
+                }
+                self.line.clear();
+                self.passing = false;
+            }
+        }
+        out
+    }
+
+    /// Whether text was pushed since the last `finish` without any code block
+    pub fn missed_code(&self) -> bool {
+        self.seen && self.blocks == 0
+    }
+
+    /// End of the text: the code still held back
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if let Some((c, len, indent)) = self.fence {
+            // An unterminated last line is code, unless it's the closing fence
+            if !self.passing && !is_closing(&self.line, c, len) {
+                out.push_str(strip_indent(&self.line, indent));
+            }
+        }
+        *self = Self::default();
+        out
+    }
+}
+
+/// The code in the fenced code blocks of the text
+pub fn extract(text: &str) -> String {
+    let mut code = CodeFilter::new();
+    let mut out = code.push(text);
+    out.push_str(&code.finish());
+    out
+}
+
+/// Fence character, length and indentation of a line opening a code block
+fn opening(line: &str) -> Option<(char, usize, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    let indent = line.len() - trimmed.len();
+    let c = trimmed.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = trimmed.chars().take_while(|&ch| ch == c).count();
+    // The info string of a backtick fence can't contain backticks
+    let info = &trimmed[len..];
+    (indent <= 3 && len >= 3 && !(c == '`' && info.contains('`'))).then_some((c, len, indent))
+}
+
+/// Whether the line closes a block opened by `len` times `c`
+fn is_closing(line: &str, c: char, len: usize) -> bool {
+    let trimmed = line.trim_start_matches(' ');
+    let run = trimmed.chars().take_while(|&ch| ch == c).count();
+    line.len() - trimmed.len() <= 3 && run >= len && trimmed[run..].trim().is_empty()
+}
+
+/// Whether the start of a line may still turn out to be a closing fence
+fn could_close(start: &str, c: char, len: usize) -> bool {
+    let trimmed = start.trim_start_matches(' ');
+    if start.len() - trimmed.len() > 3 {
+        return false;
+    }
+    let run = trimmed.chars().take_while(|&ch| ch == c).count();
+    let rest = &trimmed[run..];
+    rest.is_empty() || (run >= len && rest.trim().is_empty())
+}
+
+/// The line without up to `indent` leading spaces, the indentation of the opening fence
+fn strip_indent(line: &str, indent: usize) -> &str {
+    let spaces = line.len() - line.trim_start_matches(' ').len();
+    &line[spaces.min(indent)..]
+}
@@ -0,0 +1,112 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::tr;
+use crate::sessions::format_time;
+
+/// Information about the user's environment added to the system prompt.
+///
+/// Configured as a list in the configuration file, e.g.
+/// `context_info = ["datetime", "os", "cwd"]`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextInfo {
+    /// Current date, time and timezone
+    Datetime,
+    /// Operating system and architecture
+    Os,
+    /// The user's shell
+    Shell,
+    /// Current working directory
+    Cwd,
+}
+
+/// The system prompt part describing the environment, if any information is requested
+pub fn describe(items: &[ContextInfo]) -> Option<String> {
+    let lines: Vec<String> = items
+        .iter()
+        .filter_map(|item| {
+            Some(match item {
+                ContextInfo::Datetime => (tr().context_datetime)(&local_time()),
+                ContextInfo::Os => (tr().context_os)(&os()),
+                ContextInfo::Shell => (tr().context_shell)(&shell()?),
+                ContextInfo::Cwd => {
+                    (tr().context_cwd)(&std::env::current_dir().ok()?.to_string_lossy())
+                }
+            })
+        })
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// Current local time as `YYYY-MM-DD HH:MM:SS +HH:MM (ZONE)`
+#[cfg(unix)]
+fn local_time() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    // SAFETY: `tm` is plain data, filled in by `localtime_r` before it's used, and `tm_zone`
+    // points to a static string when set
+    let (offset, zone) = unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        let time = now as libc::time_t;
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return format!("{} UTC", format_time(now));
+        }
+        let zone = if tm.tm_zone.is_null() {
+            String::new()
+        } else {
+            std::ffi::CStr::from_ptr(tm.tm_zone)
+                .to_string_lossy()
+                .into_owned()
+        };
+        (tm.tm_gmtoff as i64, zone)
+    };
+    let sign = if offset < 0 { '-' } else { '+' };
+    let abs = offset.unsigned_abs();
+    let local = format_time(now.saturating_add_signed(offset));
+    let offset = format!("{sign}{:02}:{:02}", abs / 3600, abs % 3600 / 60);
+    if zone.is_empty() {
+        format!("{local} {offset}")
+    } else {
+        format!("{local} {offset} ({zone})")
+    }
+}
+
+/// Current time in UTC, as the local timezone isn't known on this platform
+#[cfg(not(unix))]
+fn local_time() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    format!("{} UTC", format_time(now))
+}
+
+/// Name and architecture of the operating system, e.g. `Ubuntu 22.04.3 LTS (x86_64)`
+fn os() -> String {
+    let name = std::fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|release| {
+            release.lines().find_map(|line| {
+                line.strip_prefix("PRETTY_NAME=")
+                    .map(|name| name.trim_matches('"').to_owned())
+            })
+        })
+        .unwrap_or_else(|| std::env::consts::OS.to_owned());
+    format!("{name} ({})", std::env::consts::ARCH)
+}
+
+fn shell() -> Option<String> {
+    std::env::var("SHELL")
+        .or_else(|_| std::env::var("COMSPEC"))
+        .ok()
+        .filter(|shell| !shell.is_empty())
+}
@@ -0,0 +1,94 @@
+#[cfg(unix)]
+mod unix {
+    use std::io::{Read, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{bail, Context, Result};
+    use rustyline::ExternalPrinter;
+
+    use crate::i18n::tr;
+    use crate::storage::runtime_dir;
+
+    /// Path of the control socket of the interactive session with the given name
+    fn socket_path(name: &str) -> PathBuf {
+        runtime_dir().join("sockets").join(format!("{name}.sock"))
+    }
+
+    /// A control socket accepting messages from `heygpt send` for an interactive session.
+    /// The socket is removed when it's dropped.
+    pub struct ControlSocket {
+        path: PathBuf,
+        /// Messages received and not yet added to the conversation
+        pub received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ControlSocket {
+        /// Listen on the socket of the given name, announcing received messages with the printer
+        pub fn listen(
+            name: &str,
+            mut printer: impl ExternalPrinter + Send + 'static,
+        ) -> Result<Self> {
+            let path = socket_path(name);
+            if UnixStream::connect(&path).is_ok() {
+                bail!("Another session is listening as {name}");
+            }
+            // Left behind by a session which didn't exit cleanly
+            let _ = std::fs::remove_file(&path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let listener = UnixListener::bind(&path)
+                .with_context(|| format!("Failed to listen on {}", path.display()))?;
+
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let queue = received.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else {
+                        continue;
+                    };
+                    let mut message = String::new();
+                    if stream.read_to_string(&mut message).is_err() || message.trim().is_empty() {
+                        continue;
+                    }
+                    let _ = stream.write_all(b"ok");
+                    let _ = printer.print((tr().message_received)(message.trim()));
+                    queue.lock().unwrap().push(message.trim().to_owned());
+                }
+            });
+            Ok(Self { path, received })
+        }
+    }
+
+    impl Drop for ControlSocket {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    /// Send a message to the interactive session listening with the given name
+    pub fn send(name: &str, message: &str) -> Result<()> {
+        let path = socket_path(name);
+        let mut stream = UnixStream::connect(&path)
+            .with_context(|| format!("No session is listening as {name}"))?;
+        stream.write_all(message.as_bytes())?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        let mut ack = String::new();
+        stream.read_to_string(&mut ack)?;
+        if ack != "ok" {
+            bail!("The session {name} didn't accept the message");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{send, ControlSocket};
+
+/// Control sockets need Unix domain sockets
+#[cfg(not(unix))]
+pub fn send(_name: &str, _message: &str) -> anyhow::Result<()> {
+    anyhow::bail!("Control sockets aren't supported on this platform")
+}
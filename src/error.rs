@@ -0,0 +1,100 @@
+use std::fmt;
+
+use reqwest::{Response, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+
+/// A request rejected by the API
+#[derive(Debug)]
+pub struct ApiFailure {
+    pub status: StatusCode,
+    /// Error type reported by the API, e.g. `invalid_request_error`
+    pub code: String,
+    pub message: String,
+    /// Request ID assigned by the API, for support requests
+    pub request_id: Option<String>,
+}
+
+impl ApiFailure {
+    /// Read the error from a response with a non-success status
+    pub async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let request_id = response
+            .headers()
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+        let body = response.text().await.unwrap_or_default();
+        let reason = status.canonical_reason().unwrap_or("error").to_owned();
+        // `{"error": {"type": ..., "message": ...}}` of OpenAI and Anthropic, with `status`
+        // instead of `type` for Gemini, and `{"error": "..."}` of Ollama
+        let error = serde_json::from_str::<Value>(&body).map(|mut v| v["error"].take());
+        let (code, message) = match error {
+            Ok(Value::String(message)) => (reason, message),
+            Ok(error) if error["message"].is_string() => (
+                error["type"]
+                    .as_str()
+                    .or(error["status"].as_str())
+                    .map_or(reason, str::to_owned),
+                error["message"].as_str().unwrap().to_owned(),
+            ),
+            _ => (reason, body),
+        };
+        Self {
+            status,
+            code,
+            message,
+            request_id,
+        }
+    }
+
+    /// Whether retrying the same request later may succeed
+    pub fn retryable(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS || self.status.is_server_error()
+    }
+}
+
+impl fmt::Display for ApiFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for ApiFailure {}
+
+/// An error as printed to stderr with `--output json`
+#[derive(Serialize, Debug)]
+pub struct ErrorJson {
+    pub code: String,
+    pub message: String,
+    pub retryable: bool,
+    pub request_id: Option<String>,
+}
+
+impl From<&anyhow::Error> for ErrorJson {
+    fn from(err: &anyhow::Error) -> Self {
+        let message = format!("{err:#}");
+        if let Some(e) = err.downcast_ref::<ApiFailure>() {
+            Self {
+                code: e.code.clone(),
+                message: e.message.clone(),
+                retryable: e.retryable(),
+                request_id: e.request_id.clone(),
+            }
+        } else if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+            Self {
+                code: "network_error".to_owned(),
+                message,
+                retryable: e.is_timeout() || e.is_connect(),
+                request_id: None,
+            }
+        } else {
+            Self {
+                code: "error".to_owned(),
+                message,
+                retryable: false,
+                request_id: None,
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "scripts")]
+use crate::scripts::Scripts;
+
+/// Post-processing applied to a response before it's printed in one-shot mode.
+///
+/// Configured as a list in the configuration file, e.g.
+/// `filters = ["strip_markdown", { command = "fmt -w 80" }]`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// Remove Markdown markup, keeping the text
+    StripMarkdown,
+    /// Replace runs of blank lines by a single one
+    CollapseBlankLines,
+    /// Pipe the response through a shell command
+    Command(String),
+    /// Transform the response with `fn transform(text)` of a user script
+    Script(String),
+}
+
+/// Apply the filters in order
+pub fn apply(filters: &[Filter], content: String) -> Result<String> {
+    // Loaded on first use
+    #[cfg(feature = "scripts")]
+    let mut scripts = None;
+    filters
+        .iter()
+        .try_fold(content, |content, filter| match filter {
+            Filter::StripMarkdown => Ok(strip_markdown(&content)),
+            Filter::CollapseBlankLines => Ok(collapse_blank_lines(&content)),
+            Filter::Command(cmd) => run_command(cmd, &content),
+            #[cfg(feature = "scripts")]
+            Filter::Script(name) => {
+                if scripts.is_none() {
+                    scripts = Some(Scripts::load()?);
+                }
+                scripts.as_ref().unwrap().transform(name, content)
+            }
+            #[cfg(not(feature = "scripts"))]
+            Filter::Script(name) => {
+                bail!(
+                    "heygpt was built without the `scripts` feature, needed for the filter {name}"
+                )
+            }
+        })
+}
+
+/// Compile the patterns of `strip_patterns` in the config file
+pub fn compile_strip_patterns(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|p| Regex::new(p).with_context(|| format!("Invalid strip pattern `{p}`")))
+        .collect()
+}
+
+/// Remove the text matching the patterns, e.g. disclaimers appended by a provider
+pub fn strip(patterns: &[Regex], content: String) -> String {
+    if patterns.is_empty() {
+        return content;
+    }
+    let stripped = patterns.iter().fold(content, |content, pattern| {
+        pattern.replace_all(&content, "").into_owned()
+    });
+    stripped.trim_end().to_owned()
+}
+
+fn strip_markdown(content: &str) -> String {
+    let mut result = String::new();
+    let mut in_code_block = false;
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            result.push_str(line);
+            result.push('\n');
+            continue;
+        }
+
+        let mut line = line;
+        let hashes = line.chars().take_while(|&c| c == '#').count();
+        if hashes > 0 && line[hashes..].starts_with(' ') {
+            line = &line[hashes + 1..];
+        }
+        if let Some(rest) = line.strip_prefix("> ") {
+            line = rest;
+        }
+        result.push_str(&strip_inline_markdown(line));
+        result.push('\n');
+    }
+    result
+}
+
+/// Drop emphasis and code markers, and replace links by their text
+fn strip_inline_markdown(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**").or_else(|| rest.strip_prefix("__")) {
+            rest = after;
+        } else if c == '`' {
+            rest = &rest[1..];
+        } else if let Some((text, after)) = rest.strip_prefix('[').and_then(split_link) {
+            result.push_str(text);
+            rest = after;
+        } else {
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    result
+}
+
+/// Split `text](url) rest` into the text and the rest
+fn split_link(s: &str) -> Option<(&str, &str)> {
+    let (text, after) = s.split_once("](")?;
+    let (_, rest) = after.split_once(')')?;
+    Some((text, rest))
+}
+
+fn collapse_blank_lines(content: &str) -> String {
+    let mut result = String::new();
+    let mut blank = false;
+    for line in content.trim().lines() {
+        if line.trim().is_empty() {
+            blank = true;
+            continue;
+        }
+        if blank {
+            result.push('\n');
+            blank = false;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+pub fn run_command(cmd: &str, content: &str) -> Result<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run filter `{cmd}`"))?;
+    // Write from another thread, the command may fill its stdout before reading all input.
+    // Write errors are ignored, a command may well not read its input at all.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = content.to_owned();
+    let writer = std::thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+    if !output.status.success() {
+        bail!("Filter `{cmd}` failed with {}", output.status);
+    }
+    String::from_utf8(output.stdout).with_context(|| format!("Filter `{cmd}` output isn't UTF-8"))
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+use crate::storage::{runtime_dir, Lock};
+
+/// `--host` value picking the host with the fewest requests in flight
+pub const AUTO: &str = "auto";
+
+/// A request in flight on a host, counted by the instances picking the least loaded host.
+///
+/// An instance holds a lock on its own lease file during each request. Files of instances
+/// which exited without removing them are no longer locked, and are removed when counting.
+pub struct Lease {
+    path: PathBuf,
+    _lock: Lock,
+    // Closing the file releases the lock, so it's dropped last
+    _file: File,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Pick a host from `hosts` by name, or the least loaded one with `auto`, returning its name,
+/// its base URL and the lease counting the first request on it
+pub fn select(hosts: &BTreeMap<String, String>, host: &str) -> Result<(String, String, Lease)> {
+    if hosts.is_empty() {
+        bail!("No hosts configured, add them to [hosts] in ~/.heygpt.toml");
+    }
+    let dir = leases_dir();
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    // Counting and leasing at once, so that instances started together spread out
+    let dispatch = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join("dispatch.lock"))?;
+    let _dispatch = Lock::new(&dispatch)?;
+
+    let name = if host == AUTO {
+        let mut loads = Vec::new();
+        for name in hosts.keys() {
+            loads.push((in_flight(name)?, name));
+        }
+        // The first host in name order among the least loaded
+        let (_, name) = loads.into_iter().min().unwrap();
+        name.clone()
+    } else if hosts.contains_key(host) {
+        host.to_owned()
+    } else {
+        let names: Vec<&str> = hosts.keys().map(String::as_str).collect();
+        bail!(
+            "No host {host} in [hosts] of ~/.heygpt.toml, expected {} or {AUTO}",
+            names.join(", ")
+        );
+    };
+    let lease = lease(&name)?;
+    let url = hosts[&name].clone();
+    Ok((name, url, lease))
+}
+
+fn leases_dir() -> PathBuf {
+    runtime_dir().join("hosts")
+}
+
+/// Number of requests in flight on the host, removing the leases left by exited instances
+fn in_flight(name: &str) -> Result<usize> {
+    let entries = match std::fs::read_dir(leases_dir().join(name)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let mut count = 0;
+    for entry in entries {
+        let path = entry?.path();
+        let Ok(file) = File::open(&path) else {
+            // Removed meanwhile
+            continue;
+        };
+        if Lock::try_new(&file)?.is_some() {
+            log::debug!("Removing the stale lease {}", path.display());
+            let _ = std::fs::remove_file(&path);
+        } else {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Count a request in flight on the host until the lease is dropped
+pub fn lease(name: &str) -> Result<Lease> {
+    let dir = leases_dir().join(name);
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    let path = dir.join(std::process::id().to_string());
+    let file = File::create(&path)?;
+    let lock = Lock::new(&file)?;
+    Ok(Lease {
+        path,
+        _lock: lock,
+        _file: file,
+    })
+}
@@ -0,0 +1,352 @@
+use std::sync::OnceLock;
+
+/// Interface strings and prompt scaffolding for one language.
+///
+/// Plain labels are stored as `&'static str`; messages with parameters are stored as
+/// functions so that each language can place the parameters where its grammar needs them.
+pub struct Strings {
+    pub error: &'static str,
+    pub help_title: &'static str,
+    pub help_lines: &'static [(&'static str, &'static str)],
+    pub help_hint: &'static str,
+    pub history_title: &'static str,
+    pub retracted: &'static str,
+    pub no_message_to_retract: &'static str,
+    pub unknown_command: fn(&str) -> String,
+    pub attachments_title: &'static str,
+    pub attachment_pending: &'static str,
+    pub attachment_sent: fn(usize) -> String,
+    pub attachment_not_found: fn(&str) -> String,
+    pub reattached: fn(&str) -> String,
+    pub checkpoints_title: &'static str,
+    pub checkpoint_saved: fn(&str) -> String,
+    pub checkpoint_not_found: fn(&str) -> String,
+    pub checkpoint_tag_required: &'static str,
+    pub rolled_back: fn(&str) -> String,
+    pub message_count: fn(usize) -> String,
+    pub params_title: &'static str,
+    pub param_set: fn(&str, &str) -> String,
+    pub param_out_of_range: fn(&str, f64, f64) -> String,
+    pub param_not_bool: fn(&str) -> String,
+    pub param_not_settable: fn(&str) -> String,
+    pub set_usage: &'static str,
+    pub tags: fn(&str) -> String,
+    pub sessions_not_saved: &'static str,
+    pub api_key_required: fn(&str) -> String,
+    pub response_stopped: &'static str,
+    pub response_repeating: &'static str,
+    pub response_truncated: &'static str,
+    pub no_code_block: &'static str,
+    pub tokens_used: fn(isize, isize) -> String,
+    pub unknown_model: fn(&str, Option<&str>) -> String,
+    pub prompt_required: &'static str,
+    pub reply_language: fn(&str) -> String,
+    pub detected_language: fn(&str) -> String,
+    pub partial_saved: fn(&str) -> String,
+    pub context_datetime: fn(&str) -> String,
+    pub context_os: fn(&str) -> String,
+    pub context_shell: fn(&str) -> String,
+    pub context_cwd: fn(&str) -> String,
+    pub brief_answer: &'static str,
+    pub normal_answer: &'static str,
+    pub detailed_answer: &'static str,
+    pub a11y_input_prompt: fn(&str) -> String,
+    pub a11y_announce_role: fn(&str) -> String,
+    pub terminal_context: fn(&str, &str) -> String,
+    pub received_context: fn(&str, &str) -> String,
+    pub message_received: fn(&str) -> String,
+    pub idle_notice: fn(u64) -> String,
+    pub idle_saved: fn(u64, &str) -> String,
+    pub idle_confirm: fn(u64) -> String,
+    pub idle_not_sent: &'static str,
+    pub confirm_files: fn(&str, &str) -> String,
+    pub files_not_sent: &'static str,
+    pub stdin_context: fn(&str, &str) -> String,
+    pub attachment: fn(&str, &str, &str, &str) -> String,
+    pub image_attachment_unsupported: fn(&str) -> String,
+    pub context_summary: fn(&str) -> String,
+}
+
+pub static EN: Strings = Strings {
+    error: "ERROR",
+    help_title: "Available commands:",
+    help_lines: &[
+        ("\\?, \\help", "Show this help"),
+        ("\\b, \\back", "Retract and back to the last user message"),
+        (
+            "\\h, \\history",
+            "View current conversation history, with models using -v",
+        ),
+        (
+            "\\checkpoint",
+            "Tag the current point of the conversation, or list tags",
+        ),
+        ("\\rollback", "Revert the conversation to a tagged point"),
+        ("\\params", "Show the effective request parameters"),
+        (
+            "\\set",
+            "Change a request parameter, e.g. \\set temperature 0.2",
+        ),
+        ("\\tag", "Tag the saved session, e.g. \\tag rust,work"),
+        ("\\attachments", "List the attached files"),
+        ("\\attach", "Attach a listed file again, e.g. \\attach 0"),
+    ],
+    help_hint: "Hint: Press Ctrl-J to input newline",
+    history_title: "History:",
+    retracted: "Retracted last message",
+    no_message_to_retract: "No message to retract",
+    unknown_command: |cmd| format!("Unknown command: \\{cmd}. Enter '\\?' for help."),
+    attachments_title: "Attachments:",
+    attachment_pending: "next message",
+    attachment_sent: |i| format!("message {i}"),
+    attachment_not_found: |arg| format!("No attachment {arg}. Enter '\\attachments' to list them."),
+    reattached: |name| format!("{name} will be attached to the next message"),
+    checkpoints_title: "Checkpoints:",
+    checkpoint_saved: |tag| format!("Checkpoint '{tag}' saved"),
+    checkpoint_not_found: |tag| format!("No checkpoint '{tag}'"),
+    checkpoint_tag_required: "Usage: \\rollback <tag>",
+    rolled_back: |tag| format!("Rolled back to checkpoint '{tag}'"),
+    message_count: |n| match n {
+        1 => "1 message".to_owned(),
+        n => format!("{n} messages"),
+    },
+    params_title: "Request parameters:",
+    param_set: |name, value| match value {
+        "" => format!("Unset {name}"),
+        value => format!("Set {name} to {value}"),
+    },
+    param_out_of_range: |name, min, max| format!("{name} must be a number between {min} and {max}"),
+    param_not_bool: |name| format!("{name} must be true or false"),
+    param_not_settable: |name| {
+        format!("Unknown parameter {name}. Parameters: model, stream, temperature, top_p")
+    },
+    set_usage: "Usage: \\set <parameter> [value]",
+    tags: |tags| match tags {
+        "" => "No tags".to_owned(),
+        tags => format!("Tags: {tags}"),
+    },
+    sessions_not_saved: "Sessions aren't saved, see --save-sessions",
+    api_key_required: |var| {
+        format!("An API key is required. Please set it via the {var} environment variable or config file `$HOME/.heygpt.toml`.")
+    },
+    tokens_used: |input, output| format!("{input} tokens in, {output} out"),
+    response_stopped: "(stopped)",
+    response_repeating: "(stopped, the response repeats itself)",
+    response_truncated: "(truncated, the response reached the size limit)",
+    no_code_block: "The response contains no code block",
+    unknown_model: |model, closest| {
+        match closest {
+        Some(closest) => format!("The provider doesn't offer the model {model}, did you mean {closest}?"),
+        None => format!("The provider doesn't offer the model {model}, see `heygpt doctor` for the available models"),
+    }
+    },
+    prompt_required: "Prompt is required",
+    reply_language: |lang| {
+        format!("Always reply in {lang}, regardless of the language of the question.")
+    },
+    detected_language: |lang| format!("The user writes in {lang}. Reply in {lang}."),
+    partial_saved: |path| {
+        format!("The response failed midway, the part received was saved to {path}")
+    },
+    context_datetime: |time| format!("The current date and time is {time}."),
+    context_os: |os| format!("The user's operating system is {os}."),
+    context_shell: |shell| format!("The user's shell is {shell}."),
+    context_cwd: |cwd| format!("The user's current working directory is {cwd}."),
+    brief_answer: "Answer briefly, in a few sentences at most. Omit introductions and summaries.",
+    normal_answer: "Answer with a moderate level of detail, in a few paragraphs at most.",
+    detailed_answer: "Answer in detail, with explanations and examples where helpful.",
+    a11y_input_prompt: |role| format!("{role} message: "),
+    a11y_announce_role: |role| format!("{role} says:"),
+    terminal_context: |context, prompt| {
+        format!("Here is the recent output of my terminal:\n```\n{context}\n```\n\n{prompt}")
+    },
+    received_context: |message, prompt| {
+        format!("Message from another terminal:\n{message}\n\n{prompt}")
+            .trim_end()
+            .to_owned()
+    },
+    message_received: |message| format!("Received: {message} (press Enter to send it)"),
+    idle_notice: |minutes| format!("(idle for {minutes} min)"),
+    idle_saved: |minutes, id| format!("(idle for {minutes} min, saved as session {id})"),
+    idle_confirm: |minutes| format!("Idle for {minutes} min, send this prompt? [y/N] "),
+    idle_not_sent: "(not sent, press Up to edit it)",
+    confirm_files: |files, url| format!("Send the local files {files} to {url}?"),
+    files_not_sent: "Not sent",
+    stdin_context: |input, prompt| format!("{prompt}\n\nInput:\n```\n{}\n```", input.trim_end()),
+    attachment: |name, lang, content, prompt| {
+        format!("Here is the file {name}:\n```{lang}\n{content}\n```\n\n{prompt}")
+    },
+    image_attachment_unsupported: |name| {
+        format!(
+            "{name} is an image in a format which can't be attached, use PNG, JPEG, GIF or WebP"
+        )
+    },
+    context_summary: |summary| format!("Summary of the earlier conversation:\n\n{summary}"),
+};
+
+pub static DE: Strings = Strings {
+    error: "FEHLER",
+    help_title: "Verfügbare Befehle:",
+    help_lines: &[
+        ("\\?, \\help", "Diese Hilfe anzeigen"),
+        ("\\b, \\back", "Zurück zur letzten Benutzernachricht"),
+        (
+            "\\h, \\history",
+            "Aktuellen Gesprächsverlauf anzeigen, mit Modellen bei -v",
+        ),
+        (
+            "\\checkpoint",
+            "Aktuellen Punkt des Gesprächs markieren oder Marken auflisten",
+        ),
+        (
+            "\\rollback",
+            "Gespräch auf einen markierten Punkt zurücksetzen",
+        ),
+        ("\\params", "Wirksame Anfrageparameter anzeigen"),
+        (
+            "\\set",
+            "Anfrageparameter ändern, z. B. \\set temperature 0.2",
+        ),
+        (
+            "\\tag",
+            "Gespeicherte Sitzung markieren, z. B. \\tag rust,arbeit",
+        ),
+        ("\\attachments", "Angehängte Dateien auflisten"),
+        (
+            "\\attach",
+            "Aufgelistete Datei erneut anhängen, z. B. \\attach 0",
+        ),
+    ],
+    help_hint: "Tipp: Strg-J fügt einen Zeilenumbruch ein",
+    history_title: "Verlauf:",
+    retracted: "Letzte Nachricht zurückgezogen",
+    no_message_to_retract: "Keine Nachricht zum Zurückziehen",
+    unknown_command: |cmd| format!("Unbekannter Befehl: \\{cmd}. Gib '\\?' für Hilfe ein."),
+    attachments_title: "Anhänge:",
+    attachment_pending: "nächste Nachricht",
+    attachment_sent: |i| format!("Nachricht {i}"),
+    attachment_not_found: |arg| {
+        format!("Kein Anhang {arg}. Gib '\\attachments' zum Auflisten ein.")
+    },
+    reattached: |name| format!("{name} wird an die nächste Nachricht angehängt"),
+    checkpoints_title: "Marken:",
+    checkpoint_saved: |tag| format!("Marke '{tag}' gespeichert"),
+    checkpoint_not_found: |tag| format!("Keine Marke '{tag}'"),
+    checkpoint_tag_required: "Verwendung: \\rollback <Marke>",
+    rolled_back: |tag| format!("Auf Marke '{tag}' zurückgesetzt"),
+    message_count: |n| match n {
+        1 => "1 Nachricht".to_owned(),
+        n => format!("{n} Nachrichten"),
+    },
+    params_title: "Anfrageparameter:",
+    param_set: |name, value| match value {
+        "" => format!("{name} zurückgesetzt"),
+        value => format!("{name} auf {value} gesetzt"),
+    },
+    param_out_of_range: |name, min, max| {
+        format!("{name} muss eine Zahl zwischen {min} und {max} sein")
+    },
+    param_not_bool: |name| format!("{name} muss true oder false sein"),
+    param_not_settable: |name| {
+        format!("Unbekannter Parameter {name}. Parameter: model, stream, temperature, top_p")
+    },
+    set_usage: "Verwendung: \\set <Parameter> [Wert]",
+    tags: |tags| match tags {
+        "" => "Keine Schlagwörter".to_owned(),
+        tags => format!("Schlagwörter: {tags}"),
+    },
+    sessions_not_saved: "Sitzungen werden nicht gespeichert, siehe --save-sessions",
+    api_key_required: |var| {
+        format!("Ein API-Schlüssel wird benötigt. Bitte setze ihn über die Umgebungsvariable {var} oder in der Konfigurationsdatei `$HOME/.heygpt.toml`.")
+    },
+    tokens_used: |input, output| format!("{input} Tokens hin, {output} zurück"),
+    response_stopped: "(abgebrochen)",
+    response_repeating: "(abgebrochen, die Antwort wiederholt sich)",
+    response_truncated: "(abgeschnitten, die Antwort erreichte die Größenbegrenzung)",
+    no_code_block: "Die Antwort enthält keinen Codeblock",
+    unknown_model: |model, closest| {
+        match closest {
+        Some(closest) => format!("Der Anbieter bietet das Modell {model} nicht an, meintest du {closest}?"),
+        None => format!("Der Anbieter bietet das Modell {model} nicht an, siehe `heygpt doctor` für die verfügbaren Modelle"),
+    }
+    },
+    prompt_required: "Eine Eingabe wird benötigt",
+    reply_language: |lang| {
+        format!("Antworte immer auf {lang}, unabhängig von der Sprache der Frage.")
+    },
+    detected_language: |lang| format!("Der Benutzer schreibt auf {lang}. Antworte auf {lang}."),
+    partial_saved: |path| {
+        format!("Die Antwort brach ab, der empfangene Teil wurde in {path} gespeichert")
+    },
+    context_datetime: |time| format!("Das aktuelle Datum und die Uhrzeit sind {time}."),
+    context_os: |os| format!("Das Betriebssystem des Benutzers ist {os}."),
+    context_shell: |shell| format!("Die Shell des Benutzers ist {shell}."),
+    context_cwd: |cwd| format!("Das aktuelle Arbeitsverzeichnis des Benutzers ist {cwd}."),
+    brief_answer:
+        "Antworte kurz, in höchstens wenigen Sätzen. Lass Einleitungen und Zusammenfassungen weg.",
+    normal_answer: "Antworte mäßig ausführlich, in höchstens wenigen Absätzen.",
+    detailed_answer: "Antworte ausführlich, mit Erklärungen und Beispielen, wo sie helfen.",
+    a11y_input_prompt: |role| format!("Nachricht von {role}: "),
+    a11y_announce_role: |role| format!("{role} sagt:"),
+    terminal_context: |context, prompt| {
+        format!("Hier ist die letzte Ausgabe meines Terminals:\n```\n{context}\n```\n\n{prompt}")
+    },
+    received_context: |message, prompt| {
+        format!("Nachricht aus einem anderen Terminal:\n{message}\n\n{prompt}")
+            .trim_end()
+            .to_owned()
+    },
+    message_received: |message| format!("Empfangen: {message} (Enter sendet sie)"),
+    idle_notice: |minutes| format!("({minutes} min inaktiv)"),
+    idle_saved: |minutes, id| format!("({minutes} min inaktiv, als Sitzung {id} gespeichert)"),
+    idle_confirm: |minutes| format!("{minutes} min inaktiv, diesen Prompt senden? [j/N] "),
+    idle_not_sent: "(nicht gesendet, Pfeil nach oben zum Bearbeiten)",
+    confirm_files: |files, url| format!("Die lokalen Dateien {files} an {url} senden?"),
+    files_not_sent: "Nicht gesendet",
+    stdin_context: |input, prompt| format!("{prompt}\n\nEingabe:\n```\n{}\n```", input.trim_end()),
+    attachment: |name, lang, content, prompt| {
+        format!("Hier ist die Datei {name}:\n```{lang}\n{content}\n```\n\n{prompt}")
+    },
+    image_attachment_unsupported: |name| {
+        format!("{name} ist ein Bild in einem Format, das nicht angehängt werden kann, verwende PNG, JPEG, GIF oder WebP")
+    },
+    context_summary: |summary| format!("Zusammenfassung des bisherigen Gesprächs:\n\n{summary}"),
+};
+
+static CURRENT: OnceLock<&'static Strings> = OnceLock::new();
+
+/// Select the interface language.
+///
+/// An explicitly configured language wins; otherwise it's derived from the POSIX locale
+/// environment variables, e.g. `LANG=de_DE.UTF-8`. Unknown languages fall back to English.
+pub fn init(language: Option<&str>) {
+    let language = language.map(str::to_owned).or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|v| !v.is_empty())
+    });
+    let strings = match language.as_deref().map(primary_subtag) {
+        Some("de") => &DE,
+        _ => &EN,
+    };
+    let _ = CURRENT.set(strings);
+}
+
+/// Strings of the selected language
+pub fn tr() -> &'static Strings {
+    CURRENT.get().copied().unwrap_or(&EN)
+}
+
+/// Whether the answer to a yes/no question is yes, in any of the languages
+pub fn is_yes(answer: &str) -> bool {
+    matches!(
+        answer.trim().to_lowercase().as_str(),
+        "y" | "yes" | "j" | "ja"
+    )
+}
+
+/// Extract the language part of a locale name, e.g. `de_DE.UTF-8` -> `de`
+fn primary_subtag(locale: &str) -> &str {
+    locale.split(['_', '-', '.', '@']).next().unwrap_or(locale)
+}
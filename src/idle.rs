@@ -0,0 +1,87 @@
+use std::io::Write;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Result;
+use rustyline::error::ReadlineError;
+use rustyline::history::History;
+use rustyline::{Editor, Helper};
+
+use crate::attachment::Attachment;
+use crate::i18n::{is_yes, tr};
+use crate::model::Message;
+use crate::sessions::{SavedSession, SessionFormat};
+
+/// The conversation of an interactive session, as of when a prompt is shown
+pub enum Conversation {
+    /// Nothing to save yet
+    Empty,
+    /// Saved after every turn, as the session of the given ID
+    Saved(String),
+    /// Not saved, as `save_sessions` is off, to save in the given format
+    Unsaved(Vec<Message>, Vec<Attachment>, SessionFormat),
+}
+
+/// Watches a prompt for `idle_timeout`: once it passes without input, the conversation is
+/// saved and a notice is printed above the prompt.
+///
+/// The notice is written directly rather than with a rustyline `ExternalPrinter`, as input
+/// typed ahead isn't read while an external printer exists. The prompt is redrawn after the
+/// notice, and the line being edited on the next key press.
+pub struct IdleWatcher {
+    stop: mpsc::Sender<()>,
+    handle: JoinHandle<Option<SavedSession>>,
+}
+
+impl IdleWatcher {
+    /// Start watching the given prompt, as shown with highlighting
+    pub fn start(timeout: Duration, conversation: Conversation, prompt: String) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            if stopped.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+                return None;
+            }
+            let minutes = timeout.as_secs() / 60;
+            let (notice, saved) = match conversation {
+                Conversation::Empty => ((tr().idle_notice)(minutes), None),
+                Conversation::Saved(id) => ((tr().idle_saved)(minutes, &id), None),
+                Conversation::Unsaved(messages, attached, format) => {
+                    let mut saved = SavedSession::new();
+                    match saved
+                        .set_messages(&messages, &attached)
+                        .and_then(|()| saved.save(format))
+                    {
+                        Ok(()) => ((tr().idle_saved)(minutes, &saved.id), Some(saved)),
+                        Err(err) => (format!("{}: {err}", tr().error), None),
+                    }
+                }
+            };
+            // The terminal is in raw mode: return the carriage explicitly
+            let mut stdout = std::io::stdout().lock();
+            let _ = write!(
+                stdout,
+                "\r\x1b[K{}\r\n{prompt}",
+                console::style(notice).dim()
+            );
+            let _ = stdout.flush();
+            saved
+        });
+        Self { stop, handle }
+    }
+
+    /// Stop watching, returning the session saved when the prompt went idle, if any
+    pub fn stop(self) -> Option<SavedSession> {
+        let _ = self.stop.send(());
+        self.handle.join().ok().flatten()
+    }
+}
+
+/// Ask whether to send a prompt entered after being idle for the given time
+pub fn confirm<H: Helper, I: History>(rl: &mut Editor<H, I>, idle: Duration) -> Result<bool> {
+    match rl.readline(&(tr().idle_confirm)(idle.as_secs() / 60)) {
+        Ok(answer) => Ok(is_yes(&answer)),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
@@ -0,0 +1,68 @@
+/// Watches for key presses while a response is streamed, without waiting for Enter.
+///
+/// Line buffering and echo of the terminal are disabled while this struct exists, and
+/// restored when it is dropped. Output is processed as usual, unlike in raw mode.
+#[cfg(unix)]
+pub struct KeyWatcher(libc::termios);
+
+#[cfg(unix)]
+impl KeyWatcher {
+    /// Start watching, if stdin is a terminal
+    pub fn new() -> Option<Self> {
+        // SAFETY: `termios` is plain data, filled in by `tcgetattr` before it's used
+        unsafe {
+            let mut original = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return None;
+            }
+            let mut termios = original;
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            // Reads return immediately, also when no key was pressed
+            termios.c_cc[libc::VMIN] = 0;
+            termios.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                return None;
+            }
+            Some(Self(original))
+        }
+    }
+
+    /// Whether the key was pressed since the last call. Other keys are discarded.
+    pub fn pressed(&mut self, key: u8) -> bool {
+        let mut buf = [0u8; 64];
+        let mut pressed = false;
+        loop {
+            // SAFETY: reads at most `buf.len()` bytes into `buf`
+            let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+            if n <= 0 {
+                return pressed;
+            }
+            pressed |= buf[..n as usize].contains(&key);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for KeyWatcher {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read by `tcgetattr`
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0);
+        }
+    }
+}
+
+/// Key presses can't be watched on this platform
+#[cfg(not(unix))]
+pub struct KeyWatcher;
+
+#[cfg(not(unix))]
+impl KeyWatcher {
+    pub fn new() -> Option<Self> {
+        None
+    }
+
+    pub fn pressed(&mut self, _key: u8) -> bool {
+        false
+    }
+}
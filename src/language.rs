@@ -0,0 +1,13 @@
+/// English name of the language of a text, e.g. `German`, unless the text is too short or
+/// ambiguous to tell reliably
+#[cfg(feature = "language")]
+pub fn detect(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    info.is_reliable().then(|| info.lang().eng_name())
+}
+
+/// Without the `language` feature, the language is never detected
+#[cfg(not(feature = "language"))]
+pub fn detect(_text: &str) -> Option<&'static str> {
+    None
+}
@@ -0,0 +1,63 @@
+/// Caps the size of a response, for `--max-output-bytes` and `--max-lines`
+pub struct OutputLimit {
+    max_bytes: Option<usize>,
+    max_lines: Option<usize>,
+    bytes: usize,
+    lines: usize,
+}
+
+impl OutputLimit {
+    /// A limit, unless neither cap is set
+    pub fn new(max_bytes: Option<usize>, max_lines: Option<usize>) -> Option<Self> {
+        (max_bytes.is_some() || max_lines.is_some()).then_some(Self {
+            max_bytes,
+            max_lines,
+            bytes: 0,
+            lines: 0,
+        })
+    }
+
+    /// Add a chunk of the response, returning the length of the chunk to keep if it exceeds a
+    /// cap. A line cap keeps the newline ending the last line, and a byte cap never splits a
+    /// character.
+    pub fn push(&mut self, chunk: &str) -> Option<usize> {
+        let mut cut = None;
+        if let Some(max_bytes) = self.max_bytes {
+            if self.bytes + chunk.len() > max_bytes {
+                let mut end = max_bytes - self.bytes;
+                while !chunk.is_char_boundary(end) {
+                    end -= 1;
+                }
+                cut = Some(end);
+            }
+        }
+        if let Some(max_lines) = self.max_lines {
+            // Anything after the last newline allowed exceeds the cap
+            let end = match max_lines - self.lines {
+                0 => Some(0),
+                remaining => chunk
+                    .match_indices('\n')
+                    .nth(remaining - 1)
+                    .map(|(i, _)| i + 1),
+            };
+            if let Some(end) = end.filter(|&end| end < chunk.len()) {
+                cut = Some(cut.map_or(end, |cut: usize| cut.min(end)));
+            }
+        }
+        let kept = &chunk[..cut.unwrap_or(chunk.len())];
+        self.bytes += kept.len();
+        self.lines += kept.matches('\n').count();
+        cut
+    }
+
+    /// Cut a whole response, returning whether it exceeded a cap
+    pub fn truncate(&mut self, content: &mut String) -> bool {
+        match self.push(content) {
+            Some(cut) => {
+                content.truncate(cut);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::Parser;
 use clap_serde_derive::serde::Serialize;
 use clap_serde_derive::ClapSerde;
@@ -6,23 +6,61 @@ use console::style;
 use futures::stream::StreamExt;
 use log::{debug, trace};
 use repl_helper::ReplHelper;
-use reqwest::header::{HeaderMap, AUTHORIZATION};
 use reqwest::{Client, RequestBuilder, StatusCode};
-use reqwest_eventsource::{Event, EventSource};
 use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
 use rustyline::{Cmd, Editor, EventHandler, KeyCode, KeyEvent, Modifiers};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+mod attachment;
+mod audit;
+mod blobs;
+mod code;
+mod context_info;
+mod control;
+mod error;
+mod filter;
+mod hosts;
+mod i18n;
+mod idle;
+mod keys;
+mod language;
+mod limit;
+mod math;
 mod model;
+mod models;
+mod offline;
+mod pricing;
+mod privacy;
+mod prompt_file;
+mod provider;
+mod repetition;
 mod repl_helper;
+#[cfg(feature = "scripts")]
+mod scripts;
+mod scrollback;
+mod sessions;
 mod spinner;
+mod storage;
+mod subcommand;
+mod table;
+mod tee;
 
+use attachment::Attachment;
+use error::{ApiFailure, ErrorJson};
+use i18n::tr;
 use model::*;
+use sessions::SavedSession;
 use spinner::Spinner;
 
 /// Command-line options
-#[derive(Parser, ClapSerde, Debug, Serialize)]
-#[command(about, long_about = None, trailing_var_arg=true)]
+#[derive(Parser, ClapSerde, Debug, Serialize, schemars::JsonSchema)]
+#[serde(default)]
+#[command(about, long_about = None, trailing_var_arg=true, after_help = subcommand::HELP)]
 struct Options {
     /// Whether to use streaming API (default: true)
     #[default(true)]
@@ -34,22 +72,42 @@ struct Options {
     #[arg(long)]
     pub model: String,
 
-    /// OpenAI API key
+    /// Check the model against the models offered by the provider
+    #[default(false)]
     #[arg(
         long,
         hide_short_help = true,
-        env = "OPENAI_API_KEY",
-        hide_env_values = true
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Before sending, check that the provider offers the model, and suggest the closest name on typos instead of failing with an error from the API. The model list is cached for a day."
+    )]
+    pub check_model: bool,
+
+    /// API of the provider (default: openai)
+    #[default(provider::Provider::Openai)]
+    #[arg(
+        long,
+        value_enum,
+        hide_short_help = true,
+        long_help = "API of the provider: `openai` for OpenAI and compatible APIs, `anthropic`, `gemini`, or `ollama` for the native API of Ollama. The API base URL defaults to the provider's, and the API key is read from the provider's environment variable: OPENAI_API_KEY, ANTHROPIC_API_KEY or GEMINI_API_KEY."
+    )]
+    pub provider: provider::Provider,
+
+    /// API key
+    #[arg(
+        long,
+        hide_short_help = true,
+        long_help = "API key. Defaults to the environment variable of the provider: OPENAI_API_KEY, ANTHROPIC_API_KEY or GEMINI_API_KEY, which overrides api_key in the config file."
     )]
     pub api_key: String,
 
-    /// OpenAI API base URL
-    #[default(String::from("https://api.openai.com/v1"))]
+    /// API base URL (default: the provider's)
+    #[default(String::from(provider::openai::DEFAULT_BASE_URL))]
     #[arg(
         long,
         hide_short_help = true,
-        env = "OPENAI_API_BASE",
-        hide_env_values = true
+        long_help = "API base URL. Defaults to OPENAI_API_BASE with the openai provider, or ANTHROPIC_BASE_URL with the anthropic provider, which override api_base_url in the config file, and otherwise to the provider's."
     )]
     pub api_base_url: String,
 
@@ -82,11 +140,428 @@ We generally recommend altering this or temperature but not both."#
     #[serde(skip_deserializing)]
     pub system: Option<String>,
 
+    /// Interface language, e.g. `de` (default: derived from locale)
+    #[arg(long, hide_short_help = true)]
+    pub language: Option<String>,
+
+    /// Ask the model to reply in the given language, e.g. `German`
+    #[arg(long, hide_short_help = true)]
+    pub reply_language: Option<String>,
+
+    /// Reply in the language of the prompt
+    #[default(true)]
+    #[arg(
+        long,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        hide_short_help = true,
+        long_help = "Detect the language of each prompt and ask the model to reply in it, unless --reply-language is given. Prompts too short to tell keep the language detected last. Disable with --match-language=false."
+    )]
+    pub match_language: bool,
+
+    /// Short answers
+    #[default(false)]
+    #[arg(
+        long,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        conflicts_with_all = ["normal", "detailed"],
+        long_help = "Ask for a short answer of a few sentences, and cap it at 300 tokens."
+    )]
+    #[serde(skip_deserializing)]
+    pub brief: bool,
+
+    /// Answers of moderate length
+    #[default(false)]
+    #[arg(
+        long,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        conflicts_with_all = ["brief", "detailed"],
+        long_help = "Ask for an answer of moderate length, and cap it at 1000 tokens."
+    )]
+    #[serde(skip_deserializing)]
+    pub normal: bool,
+
+    /// Detailed answers
+    #[default(false)]
+    #[arg(
+        long,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        conflicts_with_all = ["brief", "normal"],
+        long_help = "Ask for a detailed answer with explanations and examples, without capping its length."
+    )]
+    #[serde(skip_deserializing)]
+    pub detailed: bool,
+
+    /// Accessibility mode for screen readers
+    #[default(false)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Screen-reader friendly output: no spinner, colors or in-place redraws, and role changes are announced in plain words."
+    )]
+    pub a11y: bool,
+
+    /// Render Markdown tables in responses as aligned tables (default: true)
+    #[default(true)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Render Markdown tables in responses as aligned tables fitting the terminal width, instead of raw pipes and dashes. Only applies when the output is a terminal and not in accessibility mode; the conversation keeps the Markdown."
+    )]
+    pub render_tables: bool,
+
+    /// Render LaTeX math in responses as Unicode (default: true)
+    #[default(true)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Render LaTeX math in responses, such as `$\\frac{1}{2} \\alpha^2$`, as Unicode approximations like `½ α²`, or with math_renderer from the config file. Only applies when the output is a terminal; the conversation keeps the LaTeX."
+    )]
+    pub render_math: bool,
+
+    /// Command rendering LaTeX math given on stdin for the terminal, e.g. `utftex`, instead
+    /// of the Unicode approximation, configurable in the config file only
+    #[arg(skip)]
+    pub math_renderer: Option<String>,
+
+    /// Print only the code blocks of responses
+    #[default(false)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Print only the contents of the fenced code blocks of responses, without the fences, e.g. `heygpt --code-only a script listing large files > script.sh`. Code is printed as it streams in; prose is dropped. Several blocks are separated by a blank line. The conversation keeps the whole response."
+    )]
+    #[serde(skip_deserializing)]
+    pub code_only: bool,
+
+    /// Refuse network access except to local endpoints
+    #[default(false)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Refuse any network access except to local endpoints such as Ollama or llama.cpp, and hosts listed in offline_allow in the config file. Fails right away if the API endpoint isn't local, and disables downloading attachments, crate docs and syncing with remote hosts."
+    )]
+    pub offline: bool,
+
+    /// Host from [hosts] in the config file to send requests to, or `auto`
+    #[arg(
+        long,
+        value_name = "NAME",
+        hide_short_help = true,
+        long_help = "Send requests to the host of the given name from [hosts] in the config file, e.g. one of several machines running Ollama, instead of api_base_url. With `auto`, pick the host with the fewest requests in flight from all running instances, to spread batch workloads across the hosts."
+    )]
+    pub host: Option<String>,
+
+    /// Base URLs of local inference hosts by name, for `--host`, configurable in the config
+    /// file only
+    #[arg(skip)]
+    pub hosts: BTreeMap<String, String>,
+
+    /// Hosts, or `host:port`, allowed in offline mode besides the local machine, configurable
+    /// in the config file only
+    #[arg(skip)]
+    pub offline_allow: Vec<String>,
+
+    /// Stop responses repeating the same words N times
+    #[arg(
+        long,
+        value_name = "N",
+        hide_short_help = true,
+        value_parser = clap::value_parser!(u32).range(2..),
+        long_help = "Stop a streamed response once the same sequence of 8 words appeared N times, which saves tokens when a model gets stuck in a loop. The part received so far is kept. N is at least 2."
+    )]
+    pub repetition_limit: Option<u32>,
+
+    /// Stop responses at N bytes
+    #[arg(
+        long,
+        value_name = "N",
+        hide_short_help = true,
+        long_help = "Cut a response at N bytes, which protects scripts from runaway generations. A streamed response is closed once the limit is reached, so that the provider stops generating, and the output is marked as truncated on stderr."
+    )]
+    pub max_output_bytes: Option<usize>,
+
+    /// Stop responses after N lines
+    #[arg(
+        long,
+        value_name = "N",
+        hide_short_help = true,
+        long_help = "Cut a response after N lines, which protects scripts from runaway generations. A streamed response is closed once the limit is reached, so that the provider stops generating, and the output is marked as truncated on stderr."
+    )]
+    pub max_lines: Option<usize>,
+
+    /// Show tokens, cost and latency after each response in interactive mode
+    #[default(false)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Print a footer after each response in interactive mode with the tokens used, the estimated cost, the latency and the model. The cost is estimated from a built-in price list, and omitted for unknown models."
+    )]
+    pub show_cost: bool,
+
+    /// Script-friendly one-shot mode
+    #[default(false)]
+    #[arg(
+        short,
+        long,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Script-friendly one-shot mode: no spinner or colors, only the response text is printed, a failed streaming request is retried without streaming, and redirected stdin is attached to the prompt as context, e.g. `make 2>&1 | heygpt -q why does the build fail?`."
+    )]
+    #[serde(skip_deserializing)]
+    pub question: bool,
+
+    /// Replacements for the built-in system prompts of subcommands, by subcommand name
+    #[arg(skip)]
+    pub prompts: HashMap<String, String>,
+
+    /// Summarize older messages to keep at most N messages in the conversation
+    #[arg(
+        long,
+        value_name = "N",
+        hide_short_help = true,
+        long_help = "Send at most N messages of the conversation. When exceeded, the older half is sent as a summary, so that requests of long-running sessions don't grow without bounds. The saved session keeps the whole conversation."
+    )]
+    pub context_window: Option<usize>,
+
+    /// Read the prompt from a Markdown file, with options in YAML front matter
+    #[arg(
+        short = 'p',
+        long,
+        value_name = "PATH",
+        long_help = "Read the prompt from a Markdown file. YAML front matter between `---` lines may set `model`, `temperature`, `top_p`, `system` and `files` to attach, relative to the file. Options given on the command line take precedence, and a prompt given on the command line is appended to the file's."
+    )]
+    #[serde(skip_deserializing)]
+    pub prompt_file: Option<String>,
+
+    /// Attach a file or URL to the prompt
+    #[arg(
+        long = "file",
+        value_name = "PATH|URL",
+        long_help = "Attach a file or URL to the prompt. Text files are attached as is, PDFs are converted to text with `pdftotext`. May be repeated."
+    )]
+    #[serde(skip_deserializing)]
+    pub files: Vec<String>,
+
+    /// Log every request and response to a tamper-evident file
+    #[arg(
+        long,
+        value_name = "PATH",
+        hide_short_help = true,
+        long_help = "Append every request and its response to this file as JSON lines, each entry including the hash of the previous one. `heygpt audit verify` proves that no entry was edited, inserted or removed afterwards."
+    )]
+    pub audit_log: Option<String>,
+
+    /// Append the interactive session to a Markdown transcript as it goes
+    #[arg(
+        long,
+        value_name = "PATH",
+        hide_short_help = true,
+        long_help = "Append every exchange of the interactive session to this Markdown file as soon as the response is complete, with the time of the prompt and the response, so that a record exists even if heygpt is killed before the session is saved. Attached files are listed by name."
+    )]
+    #[serde(skip_deserializing)]
+    pub tee: Option<String>,
+
+    /// Save interactive sessions (default: true)
+    #[default(true)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Save interactive sessions in the data directory, e.g. ~/.local/share/heygpt/sessions, to list and export them with `heygpt history`."
+    )]
+    pub save_sessions: bool,
+
+    /// Minutes without input after which an interactive session is saved
+    #[arg(
+        long,
+        value_name = "MINUTES",
+        hide_short_help = true,
+        long_help = "In interactive mode, when a prompt waits for input for MINUTES, save the conversation as a session, even with --save-sessions=false, and print a notice above the prompt. See also --idle-confirm."
+    )]
+    pub idle_timeout: Option<u64>,
+
+    /// Ask before sending a prompt typed after the idle timeout (default: false)
+    #[default(false)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Ask for confirmation, showing the idle time, before sending a prompt entered after --idle-timeout passed, so that a conversation left for a while isn't continued by accident with stale context."
+    )]
+    pub idle_confirm: bool,
+
+    /// Format of saved sessions, `json` or `compact` for zstd-compressed MessagePack,
+    /// configurable in the config file only
+    #[arg(skip)]
+    pub session_format: sessions::SessionFormat,
+
+    /// Profile from [profiles] in the config file to apply
+    #[arg(
+        long,
+        value_name = "NAME",
+        hide_short_help = true,
+        long_help = "Apply the settings of a profile, a table of [profiles] in the config file, e.g. [profiles.work], over the other settings of the config file. `profile = \"NAME\"` in the config file applies one by default."
+    )]
+    pub profile: Option<String>,
+
+    /// Profiles of settings by name, configurable in the config file only
+    #[arg(skip)]
+    pub profiles: BTreeMap<String, serde_json::Map<String, serde_json::Value>>,
+
+    /// Privacy of conversations: `cloud_ok`, `no_log` or `local_only`, configurable in the
+    /// config file only
+    #[arg(skip)]
+    pub privacy: privacy::Privacy,
+
+    /// Limits of the saved sessions, configurable in the config file only
+    #[arg(skip)]
+    pub retention: sessions::Retention,
+
+    /// Git remote for `heygpt sync`, configurable in the config file only
+    #[arg(skip)]
+    pub sync_remote: Option<String>,
+
+    /// Prices in USD per million input and output tokens by model name prefix, overriding the
+    /// pricing table, configurable in the config file only
+    #[arg(skip)]
+    pub pricing: BTreeMap<String, pricing::Price>,
+
+    /// URL of the pricing table downloaded by `heygpt pricing update`, configurable in the
+    /// config file only
+    #[arg(skip)]
+    pub pricing_url: Option<String>,
+
+    /// Continue the conversation of the previous one-shot invocation in this terminal
+    #[default(false)]
+    #[arg(
+        long,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Continue the conversation of the previous one-shot invocation in this terminal, if it was less than an hour ago and kept with `--keep-last-conversation`. The continued conversation is kept in turn, for further follow-ups."
+    )]
+    #[serde(skip_deserializing)]
+    pub continue_last: bool,
+
+    /// Keep the conversation of one-shot invocations for `--continue-last`
+    #[default(false)]
+    #[arg(
+        long,
+        hide_short_help = true,
+        num_args(0..=1),
+        default_missing_value = "true",
+        require_equals = true,
+        long_help = "Keep the conversation of each one-shot invocation in the cache directory, e.g. ~/.cache/heygpt/last, readable only by you, to continue it with `--continue-last`. Off with `--save-sessions=false`."
+    )]
+    pub keep_last_conversation: bool,
+
+    /// Accept messages from `heygpt send --session NAME` in interactive mode
+    #[arg(
+        long,
+        value_name = "NAME",
+        hide_short_help = true,
+        long_help = "Open a control socket named NAME in interactive mode, through which other terminals send messages into the conversation with `heygpt send --session NAME`. Received messages are added to the next prompt, or sent by pressing Enter on an empty prompt."
+    )]
+    #[serde(skip_deserializing)]
+    pub listen: Option<String>,
+
+    /// Include the last N lines of terminal output as context
+    #[arg(
+        long,
+        value_name = "N",
+        long_help = "Include the last N lines of terminal output as context. Works inside tmux, or with a terminal log named by HEYGPT_SCROLLBACK_FILE (e.g. written by `script -f`)."
+    )]
+    #[serde(skip_deserializing)]
+    pub scrollback: Option<usize>,
+
+    /// Output format of one-shot mode and errors
+    #[default(OutputFormat::Text)]
+    #[arg(
+        long,
+        value_enum,
+        hide_short_help = true,
+        long_help = "Output format. With `json`, the response in one-shot mode is printed as a JSON object with `role`, `content` and `model`, and failures as a JSON object with `code`, `message`, `retryable` and `request_id` on stderr. With `table`, a JSON response, such as an array of objects, is printed as a table, and Markdown tables in the response are aligned, also when the output is redirected."
+    )]
+    #[serde(skip_deserializing)]
+    pub output: OutputFormat,
+
+    /// Post-processing of responses in one-shot mode, configurable in the config file only
+    #[arg(skip)]
+    pub filters: Vec<filter::Filter>,
+
+    /// Regular expressions of text removed from responses, configurable in the config file only
+    #[arg(skip)]
+    pub strip_patterns: Vec<String>,
+
+    /// Information about the environment added to the system prompt: `datetime`, `os`,
+    /// `shell` and `cwd`, configurable in the config file only
+    #[arg(skip)]
+    pub context_info: Vec<context_info::ContextInfo>,
+
+    /// Example turns starting every conversation, configurable in the config file only
+    #[arg(skip)]
+    pub starters: Vec<Message>,
+
     /// The prompt to ask. Leave it empty to activate interactive mode
     #[serde(skip_deserializing)]
     pub prompt: Vec<String>,
 }
 
+#[derive(
+    clap::ValueEnum,
+    Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    Text,
+    Json,
+    Table,
+}
+
+const SUMMARY_PROMPT: &str = "\
+Summarize the following conversation for the assistant continuing it. Keep facts, decisions, \
+open questions and the user's preferences; drop pleasantries. Be concise.";
+
+/// How often to check for `e` while a response is streamed in interactive mode
+const KEY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
 const CONFIG_FILE: &str = ".heygpt.toml";
 const READLINE_HISTORY: &str = ".heygpt_history";
 
@@ -94,27 +569,143 @@ const READLINE_HISTORY: &str = ".heygpt_history";
 async fn main() -> Result<()> {
     env_logger::init();
 
-    let config_file_path = dirs::home_dir().unwrap().join(CONFIG_FILE);
-    let options = if config_file_path.exists() {
+    let config_file_path = config_file_path();
+    let mut options = if config_file_path.exists() {
         let config_file = std::fs::read_to_string(&config_file_path)?;
-        let options = toml::from_str::<<Options as ClapSerde>::Opt>(&config_file)?;
+        let options = parse_config(&config_file)?;
         debug!("Loaded config file: {}", &config_file);
-        Options::from(options).merge_clap()
+        Options::from(options)
     } else {
-        Options::parse()
+        Options::default()
+    };
+    let mut cli = <<Options as ClapSerde>::Opt as Parser>::parse();
+    if let Some(name) = cli.profile.clone().flatten().or(options.profile.clone()) {
+        let Some(profile) = options.profiles.get(&name) else {
+            bail!("No profile {name} in [profiles] of ~/.heygpt.toml");
+        };
+        let profile: <Options as ClapSerde>::Opt =
+            serde_json::from_value(serde_json::Value::Object(profile.clone()))
+                .with_context(|| format!("Invalid profile {name}"))?;
+        debug!("Applying profile {name}");
+        options = options.merge(profile);
+    }
+    if let Some(Some(path)) = &cli.prompt_file {
+        let prompt_file = prompt_file::PromptFile::load(path)?;
+        debug!("Loaded prompt file: {:?}", &prompt_file);
+        prompt_file.apply(&mut options, &mut cli);
+    }
+    let api_key_given = cli.api_key.is_some();
+    let api_base_url_given = cli.api_base_url.is_some();
+    let mut options = options.merge(cli);
+    // The provider's environment variables override the config file, but not the command line,
+    // and the key of one provider is never sent to another
+    let provider = options.provider;
+    let env = |var: Option<&str>| {
+        var.and_then(|var| std::env::var(var).ok())
+            .filter(|v| !v.is_empty())
     };
+    if let (false, Some(key)) = (api_key_given, env(provider.api_key_env())) {
+        options.api_key = key;
+    }
+    if let (false, Some(url)) = (api_base_url_given, env(provider.base_url_env())) {
+        options.api_base_url = url;
+    }
 
     debug!("Final options: {:?}", &options);
 
-    if options.api_key.is_empty() {
-        bail!("OpenAI API key is required. Please set it via OPENAI_API_KEY environment variable or config file `$HOME/.heygpt.toml`.");
+    let output = options.output;
+    let result = run(options).await;
+    if let (Err(err), OutputFormat::Json) = (&result, output) {
+        eprintln!("{}", serde_json::to_string(&ErrorJson::from(err))?);
+        std::process::exit(1);
+    }
+    result
+}
+
+fn config_file_path() -> std::path::PathBuf {
+    dirs::home_dir().unwrap().join(CONFIG_FILE)
+}
+
+fn parse_config(content: &str) -> Result<<Options as ClapSerde>::Opt> {
+    Ok(toml::from_str(content)?)
+}
+
+async fn run(mut options: Options) -> Result<()> {
+    i18n::init(options.language.as_deref());
+
+    let provider = options.provider;
+    if options.api_base_url == provider::openai::DEFAULT_BASE_URL {
+        options.api_base_url = provider.default_base_url().to_owned();
+    }
+
+    let host = match &options.host {
+        Some(host) => {
+            let (name, url, lease) = hosts::select(&options.hosts, host)?;
+            debug!("Using host {name}: {url}");
+            options.api_base_url = url;
+            Some((name, lease))
+        }
+        None => None,
+    };
+
+    privacy::enforce(&mut options)?;
+
+    if options.a11y || options.question {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+
+    let command = subcommand::parse(&options.prompt).unwrap_or_else(|err| err.exit());
+    if options.api_key.is_empty()
+        && provider.needs_api_key()
+        && subcommand::needs_api_key(command.as_ref())
+    {
+        bail!((tr().api_key_required)(
+            provider.api_key_env().unwrap_or("OPENAI_API_KEY")
+        ));
+    }
+
+    if subcommand::needs_api_key(command.as_ref()) {
+        offline::check_url(&options, "API endpoint", &options.api_base_url)?;
+        // `doctor` checks the model itself, and reports more than a failure
+        if options.check_model && !matches!(command, Some(subcommand::Command::Doctor(_))) {
+            models::check(&options).await?;
+        }
     }
 
     let is_stdout = atty::is(atty::Stream::Stdout);
     let is_stdin = atty::is(atty::Stream::Stdin);
 
+    let context = options.scrollback.map(scrollback::capture).transpose()?;
+    privacy::confirm_files(&options, &options.files)?;
+    let mut attachments = Vec::new();
+    for file in &options.files {
+        if file.starts_with("http://") || file.starts_with("https://") {
+            offline::check_url(&options, "attachment", file)?;
+        }
+        attachments.push(Attachment::load(file).await?);
+    }
+
     let mut session = Session::new(options, is_stdin, is_stdout);
-    if !session.is_interactive() {
+    if let Some((name, lease)) = host {
+        session.host = Some(name);
+        session.host_lease = Some(lease);
+    }
+    session.context = context;
+    session.attachments = attachments;
+    session.strip_patterns = filter::compile_strip_patterns(&session.options.strip_patterns)?;
+    if session.options.code_only {
+        // Code isn't rendered
+        session.code = Some(code::CodeFilter::new());
+    } else if session.options.render_tables && is_stdout && !session.options.a11y {
+        session.tables = Some(table::TableBuffer::new());
+    }
+    if session.options.render_math && is_stdout && !session.options.code_only {
+        session.math = Some(math::MathBuffer::new(session.options.math_renderer.clone()));
+    }
+    if let Some(command) = command {
+        subcommand::run(&mut session, command).await?;
+    } else if !session.is_interactive() {
         session.run_one_shot().await?;
     } else {
         session.run_interactive().await?;
@@ -123,6 +714,15 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Summary of the older messages of the conversation, sent instead of them once it exceeds
+/// `--context-window`
+struct Summary {
+    /// Range of the summarized messages
+    start: usize,
+    end: usize,
+    message: Message,
+}
+
 struct Session {
     /// Command-line options
     options: Options,
@@ -138,6 +738,70 @@ struct Session {
 
     /// Spinner holder
     spinner: Option<Spinner>,
+
+    /// Terminal output to be attached to the next user message
+    context: Option<String>,
+
+    /// Files to be attached to the next user message
+    attachments: Vec<Attachment>,
+
+    /// Files attached to sent messages
+    attached: Vec<Attachment>,
+
+    /// Format of the response requested from the API
+    response_format: Option<ResponseFormat>,
+
+    /// File receiving a copy of the printed response content
+    output_file: Option<std::fs::File>,
+
+    /// Keeps only the code blocks of the printed responses, with `--code-only`
+    code: Option<code::CodeFilter>,
+
+    /// Renders Markdown tables in the printed responses, if enabled
+    tables: Option<table::TableBuffer>,
+
+    /// Renders LaTeX math in the printed responses, if enabled
+    math: Option<math::MathBuffer>,
+
+    /// Tagged points of the conversation, as the number of messages at that point
+    checkpoints: Vec<(String, usize)>,
+
+    /// Summary of the messages left out of requests by `--context-window`. The conversation
+    /// keeps them, so that the saved session is complete.
+    summary: Option<Summary>,
+
+    /// Host picked by `--host`, counted as in flight during each request
+    host: Option<String>,
+
+    /// Lease taken when picking the host, for the first request, so that instances started
+    /// together spread out
+    host_lease: Option<hosts::Lease>,
+
+    /// Where the interactive session is saved, unless disabled by `--save-sessions=false`
+    saved_session: Option<SavedSession>,
+
+    /// Turns of `starters` from the config file, sent after the system prompt but neither
+    /// shown nor saved
+    starters: Vec<Message>,
+
+    /// Compiled `strip_patterns`
+    strip_patterns: Vec<regex::Regex>,
+
+    /// Messages received through the control socket, to be added to the next user message
+    received: Arc<Mutex<Vec<String>>>,
+
+    /// Language detected in the prompts, to reply in with `match_language`
+    prompt_language: Option<&'static str>,
+
+    /// User scripts defining commands, in interactive mode
+    #[cfg(feature = "scripts")]
+    scripts: Option<scripts::Scripts>,
+
+    /// Prompts sent by script commands, to be sent as if entered
+    queued_prompts: VecDeque<String>,
+
+    /// Transcript of the interactive session, with `--tee`
+    tee: Option<tee::Tee>,
 }
 
 impl Session {
@@ -148,42 +812,163 @@ impl Session {
             is_stdout,
             messages: Vec::new(),
             spinner: None,
+            context: None,
+            attachments: Vec::new(),
+            attached: Vec::new(),
+            response_format: None,
+            output_file: None,
+            code: None,
+            tables: None,
+            math: None,
+            checkpoints: Vec::new(),
+            summary: None,
+            host: None,
+            host_lease: None,
+            saved_session: None,
+            starters: Vec::new(),
+            strip_patterns: Vec::new(),
+            received: Arc::default(),
+            prompt_language: None,
+            #[cfg(feature = "scripts")]
+            scripts: None,
+            queued_prompts: VecDeque::new(),
+            tee: None,
         }
     }
 
     pub fn is_interactive(&self) -> bool {
         // Enter interactive mode if prompt is empty and no redirection
-        self.options.prompt.is_empty() && self.is_stdout && self.is_stdin
+        self.options.prompt.is_empty() && self.is_stdout && self.is_stdin && !self.options.question
+    }
+
+    /// Whether to show a spinner while waiting for a response
+    fn show_spinner(&self) -> bool {
+        self.is_stdout && !self.options.a11y && !self.options.question
     }
 
     pub async fn run_one_shot(&mut self) -> Result<()> {
+        self.starters = self.options.starters.clone();
         let prompt = if !self.options.prompt.is_empty() {
-            self.options.prompt.join(" ")
+            let prompt = self.options.prompt.join(" ");
+            // Stdin is left alone otherwise, e.g. for `while read` loops
+            let input = if self.options.question {
+                self.read_stdin()?
+            } else {
+                None
+            };
+            match input {
+                Some(input) if !input.trim().is_empty() => (tr().stdin_context)(&input, &prompt),
+                _ => prompt,
+            }
         } else if !self.is_stdin {
             std::io::read_to_string(std::io::stdin())?
         } else {
-            bail!("Prompt is required")
+            bail!(tr().prompt_required)
         };
+        // The question rather than the input it's about
+        if self.options.prompt.is_empty() {
+            self.detect_language(&prompt);
+        } else {
+            self.detect_language(&self.options.prompt.join(" "));
+        }
+
+        if self.options.continue_last {
+            self.messages = sessions::load_last_conversation();
+        }
+
+        let message = if self.options.filters.is_empty()
+            && self.strip_patterns.is_empty()
+            && self.options.output == OutputFormat::Text
+        {
+            self.ask(self.options.system.clone(), prompt).await?
+        } else {
+            // The whole response is needed to filter it, so it can't be streamed
+            self.push_prompt(self.options.system.clone(), prompt);
+            let message = self.complete().await?;
+            let mut content = filter::apply(&self.options.filters, message.content.clone())?;
+            if self.code.take().is_some() {
+                // Extracted here, so that the JSON and table output hold the code only
+                content = code::extract(&content);
+                if content.is_empty() {
+                    eprintln!("{}", tr().no_code_block);
+                }
+            }
+            if self.options.output == OutputFormat::Json {
+                let json = serde_json::json!({
+                    "role": message.role,
+                    "content": content,
+                    "model": message.meta.as_ref().map(|m| &m.model),
+                });
+                content = serde_json::to_string(&json)?;
+            } else if self.options.output == OutputFormat::Table {
+                content = table::render_json(&content)
+                    .unwrap_or_else(|| table::render_markdown(&content));
+            }
+            if !content.ends_with('\n') {
+                content.push('\n');
+            }
+            self.print_content(&content)?;
+            self.finish_content();
+            if message.meta.as_ref().is_some_and(|meta| meta.truncated) {
+                self.notice_truncated();
+            }
+            message
+        };
+
+        if self.options.save_sessions
+            && (self.options.keep_last_conversation || self.options.continue_last)
+        {
+            self.messages.push(message);
+            sessions::save_last_conversation(&self.messages)?;
+        }
+        Ok(())
+    }
 
-        if let Some(system_prompt) = &self.options.system {
+    /// Complete a conversation made of the given system prompt and user prompt.
+    /// Meanwhile, output the response to stdout.
+    pub async fn ask(&mut self, system_prompt: Option<String>, prompt: String) -> Result<Message> {
+        self.push_prompt(system_prompt, prompt);
+        self.complete_and_print().await
+    }
+
+    /// Add the user prompt to the conversation, starting it with the given system prompt if
+    /// it's new
+    fn push_prompt(&mut self, system_prompt: Option<String>, prompt: String) {
+        let system_prompt = if self.messages.is_empty() {
+            self.system_prompt(system_prompt)
+        } else {
+            None
+        };
+        if let Some(system_prompt) = system_prompt {
             self.messages.push(Message {
                 role: "system".to_string(),
-                content: system_prompt.clone(),
+                content: system_prompt,
+                meta: None,
             });
         }
 
+        let content = self.attach_context(prompt);
         self.messages.push(Message {
             role: "user".to_string(),
-            content: prompt,
+            content,
+            meta: None,
         });
+    }
 
-        let _ = self.complete_and_print().await?;
-        Ok(())
+    /// Read the whole stdin if it's redirected
+    pub fn read_stdin(&self) -> Result<Option<String>> {
+        if self.is_stdin {
+            Ok(None)
+        } else {
+            Ok(Some(std::io::read_to_string(std::io::stdin())?))
+        }
     }
 
     pub async fn run_interactive(&mut self) -> Result<()> {
+        // Waiting for a prompt isn't a request in flight
+        self.host_lease = None;
         let mut rl = Editor::<repl_helper::ReplHelper, _>::new()?;
-        rl.set_helper(Some(ReplHelper::default()));
+        rl.set_helper(Some(ReplHelper));
 
         // Bind CTRL-J to newline
         rl.bind_sequence(
@@ -199,54 +984,318 @@ impl Session {
         };
         let _ = rl.load_history(&history_file);
 
+        if self.options.save_sessions {
+            self.saved_session = Some(SavedSession::new());
+        }
+        if !self.options.retention.is_unlimited() {
+            if let Err(err) = sessions::prune(&self.options.retention) {
+                println!("{}: {err}", style(tr().error).bold().red());
+            }
+        }
+        self.starters = self.options.starters.clone();
+        if let Some(path) = &self.options.tee {
+            self.tee = Some(tee::Tee::open(path)?);
+        }
+        #[cfg(feature = "scripts")]
+        match scripts::Scripts::load() {
+            Ok(scripts) => self.scripts = Some(scripts),
+            Err(err) => println!("{}: {err:#}", style(tr().error).bold().red()),
+        }
+
+        // Open for the rest of the session
+        #[cfg(unix)]
+        let _control = match &self.options.listen {
+            Some(name) => {
+                let socket = control::ControlSocket::listen(name, rl.create_external_printer()?)?;
+                self.received = socket.received.clone();
+                Some(socket)
+            }
+            None => None,
+        };
+        #[cfg(not(unix))]
+        if self.options.listen.is_some() {
+            bail!("Control sockets aren't supported on this platform");
+        }
+
         // If `--system` or `--system="..."` is specified
-        if let Some(s) = &self.options.system {
-            let system_prompt = if !s.is_empty() {
-                // If `--system="..."` is specified, use it as system prompt
-                s.clone()
-            } else {
-                // Otherwise, read system prompt interactively
-                if let Some(p) = self.read_prompt(&mut rl, "system").await? {
-                    p
-                } else {
-                    return Ok(());
-                }
-            };
+        let system_prompt = match self.options.system.clone() {
+            // If `--system="..."` is specified, use it as system prompt
+            Some(s) if !s.is_empty() => Some(s),
+            // Otherwise, read system prompt interactively
+            Some(_) => match self.read_prompt(&mut rl, &history_file, "system").await? {
+                Some(p) => Some(p),
+                None => return Ok(()),
+            },
+            None => None,
+        };
+        if let Some(system_prompt) = self.system_prompt(system_prompt) {
             self.messages.push(Message {
                 role: "system".to_string(),
                 content: system_prompt,
+                meta: None,
             });
-        };
+        }
 
         loop {
-            let prompt = if let Some(p) = self.read_prompt(&mut rl, "user").await? {
+            let prompt = if let Some(p) = self.read_prompt(&mut rl, &history_file, "user").await? {
                 p
             } else {
                 break;
             };
 
+            self.detect_language(&prompt);
+            let attachments: Vec<String> =
+                self.attachments.iter().map(|a| a.name.clone()).collect();
+            let content = self.attach_context(prompt.clone());
             self.messages.push(Message {
                 role: "user".to_string(),
-                content: prompt,
+                content,
+                meta: None,
             });
 
+            let sent = SystemTime::now();
+            let start = std::time::Instant::now();
             match self.complete_and_print().await {
-                Ok(response) => self.messages.push(response),
+                Ok(response) => {
+                    if self.options.show_cost {
+                        self.print_cost(&response, start.elapsed());
+                    }
+                    if let Some(tee) = &mut self.tee {
+                        if let Err(err) = tee.exchange(sent, &prompt, &attachments, &response) {
+                            println!("{}: {err}", style(tr().error).bold().red());
+                        }
+                    }
+                    self.messages.push(response);
+                }
                 Err(err) => {
                     let last_msg = self.messages.pop(); // remove the last message
                     assert!(last_msg.is_some());
-                    println!("{}: {err}", style("ERROR").bold().red());
+                    // Attach the files again to the next message
+                    let len = self.messages.len();
+                    let (unsent, sent) = self
+                        .attached
+                        .drain(..)
+                        .partition(|a| a.message.is_some_and(|i| i >= len));
+                    self.attached = sent;
+                    self.attachments.extend(
+                        unsent
+                            .into_iter()
+                            .map(|a: Attachment| Attachment { message: None, ..a }),
+                    );
+                    println!("{}: {err}", style(tr().error).bold().red());
                 }
             }
+
+            if let Err(err) = self.slide_context_window().await {
+                println!("{}: {err}", style(tr().error).bold().red());
+            }
+            if let Err(err) = self.save_session() {
+                println!("{}: {err}", style(tr().error).bold().red());
+            }
+        }
+
+        self.save_session()
+    }
+
+    /// Print a footer with the tokens used, the estimated cost, the latency and the model
+    fn print_cost(&self, message: &Message, latency: std::time::Duration) {
+        let Some(meta) = &message.meta else {
+            return;
+        };
+        let mut parts = Vec::new();
+        if let Some(usage) = &meta.usage {
+            parts.push((tr().tokens_used)(
+                usage.prompt_tokens,
+                usage.completion_tokens,
+            ));
+            match pricing::cost(&self.options.pricing, &meta.model, usage) {
+                Some(cost) if cost < 0.0001 => parts.push("<$0.0001".to_owned()),
+                Some(cost) => parts.push(format!("~${cost:.4}")),
+                None => {}
+            }
+        }
+        parts.push(format!("{:.1} s", latency.as_secs_f64()));
+        parts.push(meta.model.clone());
+        println!("{}", style(parts.join(" · ")).dim());
+    }
+
+    /// The conversation to save when a prompt goes idle
+    fn idle_conversation(&self) -> idle::Conversation {
+        if !self.messages.iter().any(|m| m.role == "user") || !self.options.privacy.allows_logs() {
+            return idle::Conversation::Empty;
+        }
+        match &self.saved_session {
+            Some(saved) => idle::Conversation::Saved(saved.id.clone()),
+            None => idle::Conversation::Unsaved(
+                self.messages.clone(),
+                self.attached.clone(),
+                self.options.session_format,
+            ),
+        }
+    }
+
+    /// Save the interactive session once it has a user message
+    fn save_session(&mut self) -> Result<()> {
+        let Some(saved) = &mut self.saved_session else {
+            return Ok(());
+        };
+        if !self.messages.iter().any(|m| m.role == "user") {
+            return Ok(());
+        }
+        saved.set_messages(&self.messages, &self.attached)?;
+        saved.save(self.options.session_format)
+    }
+
+    /// Summarize the older half of the conversation for requests if it exceeds
+    /// `--context-window`. The system prompt is kept as is, and a previous summary is
+    /// summarized again.
+    async fn slide_context_window(&mut self) -> Result<()> {
+        let Some(window) = self.options.context_window else {
+            return Ok(());
+        };
+        let (start, summarized) = match &self.summary {
+            Some(summary) => (summary.start, summary.end),
+            None => {
+                let system = self
+                    .messages
+                    .iter()
+                    .take_while(|m| m.role == "system")
+                    .count();
+                (system, system)
+            }
+        };
+        // Messages sent, with the summarized ones replaced by the summary
+        let sent = self.messages.len() - (summarized - start) + usize::from(self.summary.is_some());
+        if sent <= window {
+            return Ok(());
         }
 
-        rl.append_history(&history_file)?;
+        // Keep the newer half, starting with a user message
+        let Some(end) = (summarized.max(start + 1)..self.messages.len())
+            .filter(|&i| self.messages[i].role == "user")
+            .find(|&i| self.messages.len() - i <= window / 2)
+        else {
+            return Ok(());
+        };
+
+        let transcript = self
+            .summary
+            .iter()
+            .map(|summary| &summary.message)
+            .chain(&self.messages[summarized..end])
+            .map(|m| format!("{}: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let messages = std::mem::replace(
+            &mut self.messages,
+            vec![
+                Message {
+                    role: "system".to_string(),
+                    content: SUMMARY_PROMPT.to_string(),
+                    meta: None,
+                },
+                Message {
+                    role: "user".to_string(),
+                    content: transcript,
+                    meta: None,
+                },
+            ],
+        );
+        let starters = std::mem::take(&mut self.starters);
+        let previous = self.summary.take();
+        let summary = self.complete().await;
+        self.messages = messages;
+        self.starters = starters;
+        self.summary = previous;
+        let summary = summary?;
+
+        self.summary = Some(Summary {
+            start,
+            end,
+            message: Message {
+                role: "system".to_string(),
+                content: (tr().context_summary)(&summary.content),
+                meta: summary.meta,
+            },
+        });
         Ok(())
     }
 
+    /// Combine the user-provided system prompt with the prompt scaffolding derived from options
+    fn system_prompt(&self, system_prompt: Option<String>) -> Option<String> {
+        let mut parts: Vec<String> = system_prompt.into_iter().collect();
+        if let Some(lang) = &self.options.reply_language {
+            parts.push((tr().reply_language)(lang));
+        }
+        if let Some((_, instruction)) = self.length_preset() {
+            parts.push(instruction.to_owned());
+        }
+        parts.extend(context_info::describe(&self.options.context_info));
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("\n\n"))
+        }
+    }
+
+    /// Detect the language of a prompt to reply in, unless disabled or given by
+    /// `--reply-language`
+    fn detect_language(&mut self, prompt: &str) {
+        if !self.options.match_language || self.options.reply_language.is_some() {
+            return;
+        }
+        if let Some(lang) = language::detect(prompt) {
+            debug!("Detected language: {lang}");
+            self.prompt_language = Some(lang);
+        }
+    }
+
+    /// Token limit and length instruction of `--brief`, `--normal` or `--detailed`
+    fn length_preset(&self) -> Option<(Option<u32>, &'static str)> {
+        if self.options.brief {
+            Some((Some(300), tr().brief_answer))
+        } else if self.options.normal {
+            Some((Some(1000), tr().normal_answer))
+        } else if self.options.detailed {
+            Some((None, tr().detailed_answer))
+        } else {
+            None
+        }
+    }
+
+    /// Prepend received messages, pending attachments and terminal output, if any, to the prompt
+    fn attach_context(&mut self, prompt: String) -> String {
+        let received: Vec<String> = self.received.lock().unwrap().drain(..).collect();
+        let prompt = received.iter().rev().fold(prompt, |prompt, message| {
+            (tr().received_context)(message, &prompt)
+        });
+        let prompt = match self.context.take() {
+            Some(context) => (tr().terminal_context)(&context, &prompt),
+            None => prompt,
+        };
+        let attachments: Vec<Attachment> = self.attachments.drain(..).collect();
+        // Images are sent alongside the text, by `request_images`
+        let prompt = attachments
+            .iter()
+            .rev()
+            .filter(|a| a.image.is_none())
+            .fold(prompt, |prompt, a| {
+                (tr().attachment)(&a.name, &a.lang, a.content.trim_end(), &prompt)
+            });
+        // The prompt becomes the next message
+        let message = self.messages.len();
+        self.attached
+            .extend(attachments.into_iter().map(|a| Attachment {
+                message: Some(message),
+                ..a
+            }));
+        prompt
+    }
+
     async fn read_prompt<H, I>(
         &mut self,
         rl: &mut Editor<H, I>,
+        history_file: &str,
         role: &str,
     ) -> Result<Option<String>>
     where
@@ -254,17 +1303,58 @@ impl Session {
         I: rustyline::history::History,
     {
         loop {
-            let readline = rl.readline(&format!("{} => ", role));
+            if role == "user" {
+                if let Some(prompt) = self.queued_prompts.pop_front() {
+                    println!("{}{prompt}", ReplHelper.highlight_prompt("user => ", true));
+                    return Ok(Some(prompt));
+                }
+            }
+            let idle_timeout = self
+                .options
+                .idle_timeout
+                .filter(|_| role == "user")
+                .map(|minutes| std::time::Duration::from_secs(minutes * 60));
+            let prompt = if self.options.a11y {
+                (tr().a11y_input_prompt)(role)
+            } else {
+                format!("{} => ", role)
+            };
+            let watcher = idle_timeout.map(|timeout| {
+                let shown = ReplHelper.highlight_prompt(&prompt, true).into_owned();
+                idle::IdleWatcher::start(timeout, self.idle_conversation(), shown)
+            });
+            let shown = std::time::Instant::now();
+            let readline = rl.readline(&prompt);
+            if let Some(saved) = watcher.and_then(idle::IdleWatcher::stop) {
+                // Keep saving the session from now on
+                self.saved_session = Some(saved);
+            }
+            let idle = idle_timeout.is_some_and(|timeout| shown.elapsed() >= timeout);
             match readline {
                 Ok(line) => {
                     if line.is_empty() {
-                        continue; // ignore empty input
+                        // Send received messages on their own, otherwise ignore empty input
+                        if role == "user" && !self.received.lock().unwrap().is_empty() {
+                            return Ok(Some(line));
+                        }
+                        continue;
                     }
                     rl.add_history_entry(line.as_str())?;
+                    // Append immediately rather than on exit. Appending takes a file lock and
+                    // merges with entries written by other running instances meanwhile.
+                    if self.options.privacy.allows_logs() {
+                        rl.append_history(history_file)?;
+                    }
 
                     if let Some(cmd) = line.strip_prefix('\\') {
                         self.run_command(cmd);
                         continue;
+                    } else if idle
+                        && self.options.idle_confirm
+                        && !idle::confirm(rl, shown.elapsed())?
+                    {
+                        println!("{}", style(tr().idle_not_sent).dim());
+                        continue;
                     } else {
                         return Ok(Some(line));
                     }
@@ -287,145 +1377,611 @@ impl Session {
     /// Complete the message sequence and returns the next message.
     /// Meanwhile, output the response to stdout.
     async fn complete_and_print(&mut self) -> Result<Message> {
-        // Build the request
-        let data = Request {
-            model: self.options.model.clone(),
-            stream: self.options.stream,
-            messages: self.messages.to_vec(),
-            temperature: self.options.temperature,
-            top_p: self.options.top_p,
-        };
+        let _lease = self.lease_host()?;
+        let req = self.build_request(self.options.stream, self.response_format.clone());
 
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            AUTHORIZATION,
-            format!("Bearer {}", self.options.api_key).parse().unwrap(),
-        );
+        // Show spinner if stdout is not redirected
+        if self.show_spinner() {
+            self.spinner = Some(Spinner::new());
+        }
 
-        let client = Client::new();
-        let req = client
-            .post(format!("{}/chat/completions", &self.options.api_base_url))
-            .headers(headers)
-            .json(&data);
+        let message = if self.options.stream {
+            self.do_stream_request(req).await?
+        } else {
+            self.do_non_stream_request(req).await?
+        };
+        self.audit(&message)?;
+        Ok(message)
+    }
 
-        debug!("Request body: {:?}", &data);
+    /// Complete the message sequence and returns the next message. Nothing is printed.
+    pub async fn complete(&mut self) -> Result<Message> {
+        let _lease = self.lease_host()?;
+        let req = self.build_request(false, self.response_format.clone());
 
-        // Show spinner if stdout is not redirected
-        if self.is_stdout {
+        if self.show_spinner() {
             self.spinner = Some(Spinner::new());
         }
 
-        if self.options.stream {
-            self.do_stream_request(req).await
-        } else {
-            self.do_non_stream_request(req).await
+        let message = self.send_non_stream_request(req).await?;
+        self.audit(&message)?;
+        Ok(message)
+    }
+
+    /// Count a request in flight on the host picked by `--host`, until the lease is dropped
+    fn lease_host(&mut self) -> Result<Option<hosts::Lease>> {
+        let Some(name) = &self.host else {
+            return Ok(None);
+        };
+        match self.host_lease.take() {
+            Some(lease) => Ok(Some(lease)),
+            None => hosts::lease(name).map(Some),
+        }
+    }
+
+    /// Complete the message sequence in JSON mode and returns the next message, along with its
+    /// content parsed as `T`. Nothing is printed.
+    pub async fn complete_json<T: DeserializeOwned>(&mut self) -> Result<(Message, T)> {
+        let response_format = self.response_format.replace(ResponseFormat::json_object());
+        let message = self.complete().await;
+        self.response_format = response_format;
+
+        let message = message?;
+        let parsed = serde_json::from_str(&message.content)
+            .with_context(|| format!("Unexpected response: {}", message.content))?;
+        Ok((message, parsed))
+    }
+
+    /// Append the request and its response to the audit log, if enabled
+    fn audit(&self, response: &Message) -> Result<()> {
+        if let Some(path) = &self.options.audit_log {
+            audit::append(&audit::log_path(path), self.request_messages(), response)?;
+        }
+        Ok(())
+    }
+
+    /// The messages to send, with the summary of `--context-window` instead of the messages it
+    /// summarizes, and the language to reply in and the starters after the system prompt
+    fn request_messages(&self) -> Vec<Message> {
+        self.request_conversation().0
+    }
+
+    /// The messages of `request_messages`, along with the index in the conversation of each
+    /// one, if it's part of it
+    fn request_conversation(&self) -> (Vec<Message>, Vec<Option<usize>>) {
+        let mut messages: Vec<(Message, Option<usize>)> = self
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.clone(), Some(i)))
+            .collect();
+        if let Some(summary) = &self.summary {
+            messages.splice(
+                summary.start..summary.end,
+                [(summary.message.clone(), None)],
+            );
+        }
+        let system = messages
+            .iter()
+            .take_while(|(m, _)| m.role == "system")
+            .count();
+        let language = self.prompt_language.map(|lang| Message {
+            role: "system".to_string(),
+            content: (tr().detected_language)(lang),
+            meta: None,
+        });
+        messages.splice(
+            system..system,
+            language
+                .into_iter()
+                .chain(self.starters.iter().cloned())
+                .map(|m| (m, None)),
+        );
+        messages.into_iter().unzip()
+    }
+
+    /// Images attached to the messages of `request_conversation`, by index. Those of summarized
+    /// messages aren't sent anymore.
+    fn request_images(&self, origins: &[Option<usize>]) -> BTreeMap<usize, Vec<Image>> {
+        let mut images: BTreeMap<usize, Vec<Image>> = BTreeMap::new();
+        for a in &self.attached {
+            let (Some(mime), Some(message)) = (&a.image, a.message) else {
+                continue;
+            };
+            let Some(i) = origins.iter().position(|&o| o == Some(message)) else {
+                continue;
+            };
+            images.entry(i).or_default().push(Image {
+                mime: mime.clone(),
+                data: a.content.clone(),
+            });
         }
+        images
+    }
+
+    fn build_request(
+        &self,
+        stream: bool,
+        response_format: Option<ResponseFormat>,
+    ) -> RequestBuilder {
+        let (messages, origins) = self.request_conversation();
+        let images = self.request_images(&origins);
+        let data = Request {
+            model: self.options.model.clone(),
+            stream,
+            messages,
+            temperature: self.options.temperature,
+            top_p: self.options.top_p,
+            max_tokens: self.length_preset().and_then(|(max_tokens, _)| max_tokens),
+            response_format,
+            stream_options: (stream && self.options.show_cost).then_some(StreamOptions {
+                include_usage: true,
+            }),
+            images,
+        };
+
+        debug!("Request body: {:?}", &data);
+
+        let provider = self.options.provider;
+        let client = Client::new();
+        client
+            .post(provider.url(&self.options.api_base_url, &data.model, stream))
+            .headers(provider.headers(&self.options.api_key))
+            .json(&provider.body(&data))
     }
 
     async fn do_stream_request(&mut self, req: RequestBuilder) -> Result<Message> {
         let mut full_message = Message::default();
+        let provider = self.options.provider;
 
-        let mut es = EventSource::new(req)?;
-        while let Some(event) = es.next().await {
-            self.spinner = None;
-            match event {
-                Ok(Event::Open) => {
-                    debug!("response stream opened");
-                }
-                Ok(Event::Message(message)) if message.data == "[DONE]" => {
-                    debug!("response stream ended with [DONE]");
-                    println!();
+        let response = match req.send().await {
+            Ok(response) if response.status() == StatusCode::OK => Ok(response),
+            Ok(response) => Err(ApiFailure::from_response(response).await.into()),
+            Err(err) => Err(anyhow::Error::from(err)),
+        };
+        self.spinner = None;
+        let response = match response {
+            Ok(response) => response,
+            // Some servers don't support streaming. Retry, as nothing was printed yet.
+            Err(err) if self.options.question => {
+                debug!("retrying without streaming: {err:#}");
+                let req = self.build_request(false, self.response_format.clone());
+                return self.do_non_stream_request(req).await;
+            }
+            Err(err) => return Err(err),
+        };
+        debug!("response stream opened");
+
+        let mut body = response.bytes_stream();
+        let mut decoder = provider::Decoder::new(provider);
+        let mut ended = false;
+        // Pressing `e` stops the response, to edit a follow-up prompt right away
+        let mut keys = self.is_interactive().then(keys::KeyWatcher::new).flatten();
+        let mut key_poll = tokio::time::interval(KEY_POLL_INTERVAL);
+        let mut repetition = self
+            .options
+            .repetition_limit
+            // Below 2, every sequence would count as a repetition, also from the config file
+            .map(|limit| repetition::RepetitionGuard::new(limit.max(2) as usize));
+        let mut limit = self.output_limit();
+        loop {
+            let data = match decoder.next_event() {
+                Some(data) => data,
+                None if ended => {
+                    debug!("response stream ended");
+                    self.print_content("\n")?;
                     break;
                 }
-                Ok(Event::Message(message)) => {
-                    trace!("response stream message: {:?}", &message);
-                    let message: ResponseStreamMessage = serde_json::from_str(&message.data)?;
-                    let delta = message.choices.into_iter().next().unwrap().delta;
-                    if let Some(role) = delta.role {
-                        full_message.role.push_str(&role);
-
-                        if self.is_interactive() {
-                            print!("{} => ", style(role).bold().green());
-                            std::io::stdout().flush().unwrap();
+                None => {
+                    let bytes = tokio::select! {
+                        bytes = body.next() => bytes,
+                        _ = key_poll.tick(), if keys.is_some() => {
+                            if keys.as_mut().is_some_and(|keys| keys.pressed(b'e')) {
+                                if full_message.role.is_empty() {
+                                    full_message.role = "assistant".to_string();
+                                }
+                                self.finish_content();
+                                println!("\n{}", style(tr().response_stopped).dim());
+                                break;
+                            }
+                            continue;
                         }
-                    }
-                    if let Some(mut content) = delta.content {
-                        // Trick: Sometimes the response starts with a newline. Strip it here.
-                        if content.starts_with('\n') && full_message.content.is_empty() {
-                            content = content.trim_start().to_owned();
+                    };
+                    match bytes {
+                        Some(Ok(bytes)) => {
+                            decoder.push(&bytes);
+                            continue;
+                        }
+                        Some(Err(err)) => {
+                            debug!("response stream error: {}", err);
+                            // Retry if nothing was printed yet
+                            if self.options.question && full_message.content.is_empty() {
+                                debug!("retrying without streaming");
+                                let req = self.build_request(false, self.response_format.clone());
+                                return self.do_non_stream_request(req).await;
+                            }
+                            self.salvage(&full_message);
+                            return Err(err.into());
+                        }
+                        None => {
+                            ended = true;
+                            match decoder.finish() {
+                                Some(data) => data,
+                                None => continue,
+                            }
                         }
-                        print!("{}", content);
-                        full_message.content.push_str(&content);
                     }
-                    std::io::stdout().flush().unwrap();
                 }
+            };
+            trace!("response stream event: {}", &data);
+            let chunk = match provider.parse_chunk(&data) {
+                Ok(chunk) => chunk,
                 Err(err) => {
-                    es.close();
-                    debug!("EventSource stream error: {}", err);
-                    return Err(err.into());
+                    self.salvage(&full_message);
+                    return Err(err);
+                }
+            };
+            let meta = full_message.meta.get_or_insert_with(|| {
+                self.message_meta(chunk.model.clone().unwrap_or(self.options.model.clone()))
+            });
+            meta.usage = ResponseUsage::merge(meta.usage.take(), chunk.usage);
+            if let Some(role) = chunk.role.filter(|_| full_message.role.is_empty()) {
+                full_message.role = role;
+                if self.is_interactive() {
+                    self.print_role(&full_message.role);
+                    std::io::stdout().flush().unwrap();
+                }
+            }
+            if let Some(mut content) = chunk.content {
+                // Trick: Sometimes the response starts with a newline. Strip it here.
+                if content.starts_with('\n') && full_message.content.is_empty() {
+                    content = content.trim_start().to_owned();
+                }
+                let cut = limit.as_mut().and_then(|limit| limit.push(&content));
+                if let Some(cut) = cut {
+                    content.truncate(cut);
+                }
+                self.print_content(&content)?;
+                full_message.content.push_str(&content);
+                if cut.is_some() {
+                    // Dropping the response closes the stream, which stops the generation
+                    if !full_message.content.ends_with('\n') {
+                        self.print_content("\n")?;
+                    }
+                    if let Some(meta) = &mut full_message.meta {
+                        meta.truncated = true;
+                    }
+                    self.finish_content();
+                    self.notice_truncated();
+                    break;
+                }
+                if repetition.as_mut().is_some_and(|r| r.push(&content)) {
+                    self.print_content("\n")?;
+                    if self.is_interactive() {
+                        println!("{}", style(tr().response_repeating).dim());
+                    } else {
+                        eprintln!("{}", tr().response_repeating);
+                    }
+                    break;
                 }
             }
+            std::io::stdout().flush().unwrap();
+            if chunk.done {
+                debug!("response stream ended with the last event");
+                self.print_content("\n")?;
+                break;
+            }
         }
 
+        self.finish_content();
         debug!("response stream full message: {:?}", &full_message);
+        full_message.content = filter::strip(&self.strip_patterns, full_message.content);
 
         Ok(full_message)
     }
 
     async fn do_non_stream_request(&mut self, req: RequestBuilder) -> Result<Message> {
+        let message = self.send_non_stream_request(req).await?;
+
+        if self.is_interactive() {
+            self.print_role(&message.role);
+        }
+        let truncated = message.meta.as_ref().is_some_and(|meta| meta.truncated);
+        if truncated && message.content.ends_with('\n') {
+            self.print_content(&message.content)?;
+        } else {
+            self.print_content(&format!("{}\n", &message.content))?;
+        }
+        self.finish_content();
+        std::io::stdout().flush()?;
+        if truncated {
+            self.notice_truncated();
+        }
+
+        Ok(message)
+    }
+
+    async fn send_non_stream_request(&mut self, req: RequestBuilder) -> Result<Message> {
         let response = req.send().await?;
 
         self.spinner = None;
 
         if response.status() != StatusCode::OK {
-            let r: WrappedApiError = response.json().await?;
-            return Err(anyhow!("{}: {}", r.error.r#type, r.error.message));
+            return Err(ApiFailure::from_response(response).await.into());
         }
 
-        let response: ResponseMessage = response.json().await?;
-        debug!("response message: {:?}", &response);
+        let body = response.text().await?;
+        debug!("response body: {}", &body);
+        let reply = self.options.provider.parse_reply(&body)?;
 
-        let mut message = response.choices[0].message.clone();
+        let model = reply.model.unwrap_or(self.options.model.clone());
+        let mut message = Message {
+            role: "assistant".to_string(),
+            content: reply.content,
+            meta: Some(MessageMeta {
+                usage: reply.usage,
+                ..self.message_meta(model)
+            }),
+        };
 
         // Trick: Sometimes the response starts with a newline. Strip it here.
         if message.content.starts_with('\n') {
             message.content = message.content.trim_start().to_owned();
         }
+        message.content = filter::strip(&self.strip_patterns, message.content);
+        if let Some(mut limit) = self.output_limit() {
+            if limit.truncate(&mut message.content) {
+                message.meta.as_mut().unwrap().truncated = true;
+            }
+        }
+
+        Ok(message)
+    }
+
+    /// The cap of `--max-output-bytes` and `--max-lines` on a response, if any
+    fn output_limit(&self) -> Option<limit::OutputLimit> {
+        limit::OutputLimit::new(self.options.max_output_bytes, self.options.max_lines)
+    }
 
+    /// Tell that a response was cut at `--max-output-bytes` or `--max-lines`
+    fn notice_truncated(&self) {
         if self.is_interactive() {
-            print!("{} => ", style(&message.role).bold().green());
+            println!("{}", style(tr().response_truncated).dim());
+        } else {
+            eprintln!("{}", tr().response_truncated);
         }
-        println!("{}", &message.content);
-        std::io::stdout().flush()?;
+    }
 
-        Ok(message)
+    /// Describe how a response was produced, with the model reported by the API
+    fn message_meta(&self, model: String) -> MessageMeta {
+        MessageMeta {
+            model,
+            temperature: self.options.temperature,
+            top_p: self.options.top_p,
+            provider: self.options.api_base_url.clone(),
+            usage: None,
+            truncated: false,
+        }
+    }
+
+    /// Print content of a response, and copy it to the output file if any
+    fn print_content(&mut self, content: &str) -> Result<()> {
+        let shown = match &mut self.code {
+            Some(code) => code.push(content),
+            None => content.to_owned(),
+        };
+        let shown = match &mut self.math {
+            Some(math) => math.push(&shown),
+            None => shown,
+        };
+        match &mut self.tables {
+            Some(tables) => print!("{}", tables.push(&shown)),
+            None => print!("{}", shown),
+        }
+        if let Some(file) = &mut self.output_file {
+            file.write_all(content.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Write the content of a response which failed midway to a temporary file, rather than
+    /// losing it, unless the privacy level forbids it
+    fn salvage(&mut self, partial: &Message) {
+        if partial.content.trim().is_empty() || !self.options.privacy.allows_logs() {
+            return;
+        }
+        self.finish_content();
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path =
+            std::env::temp_dir().join(format!("heygpt-partial-{time}-{}.md", std::process::id()));
+        let notice = match std::fs::write(&path, &partial.content) {
+            Ok(()) => (tr().partial_saved)(&path.display().to_string()),
+            Err(err) => format!("{}: {err}", tr().error),
+        };
+        if self.is_interactive() {
+            println!("\n{}", style(notice).dim());
+        } else {
+            if self.is_stdout {
+                // End the line of the partial response on the terminal
+                println!();
+            }
+            eprintln!("{notice}");
+        }
+    }
+
+    /// Print the text held back by the code filter, and the table and math rendering, at the
+    /// end of a response
+    fn finish_content(&mut self) {
+        let mut shown = String::new();
+        let is_interactive = self.is_interactive();
+        if let Some(code) = &mut self.code {
+            if code.missed_code() {
+                if is_interactive {
+                    println!("{}", style(tr().no_code_block).dim());
+                } else {
+                    eprintln!("{}", tr().no_code_block);
+                }
+            }
+            shown = code.finish();
+        }
+        if let Some(math) = &mut self.math {
+            shown = math.push(&shown);
+            shown.push_str(&math.finish());
+        }
+        if let Some(tables) = &mut self.tables {
+            shown = tables.push(&shown);
+            shown.push_str(&tables.finish());
+        }
+        print!("{}", shown);
+    }
+
+    /// Print the role of a response before its content
+    fn print_role(&self, role: &str) {
+        if self.options.a11y {
+            // Announce on a separate line so that screen readers don't merge it into the content
+            println!("{}", (tr().a11y_announce_role)(role));
+        } else {
+            print!("{} => ", style(role).bold().green());
+        }
     }
 
     fn run_command(&mut self, cmd: &str) {
+        let (cmd, arg) = match cmd.split_once(' ') {
+            Some((cmd, arg)) => (cmd, arg.trim()),
+            None => (cmd, ""),
+        };
         match cmd {
             "?" | "help" => {
-                println!("{}", style("Available commands:").bold());
-                println!("  \\?, \\help     Show this help");
-                println!("  \\b, \\back     Retract and back to the last user message");
-                println!("  \\h, \\history  View current conversation history");
-                println!("Hint: Press Ctrl-J to input newline");
+                println!("{}", style(tr().help_title).bold());
+                for (cmd, description) in tr().help_lines {
+                    println!("  {cmd:<13} {description}");
+                }
+                #[cfg(feature = "scripts")]
+                for (name, description) in self.scripts.iter().flat_map(|s| s.commands()) {
+                    let cmd = format!("\\{name}");
+                    println!("  {cmd:<13} {description}");
+                }
+                println!("{}", tr().help_hint);
             }
             "b" | "back" => match self.retract() {
-                Ok(()) => println!("Retracted last message"),
-                Err(err) => println!("{}: {err}", style("ERROR").bold().red()),
+                Ok(()) => println!("{}", tr().retracted),
+                Err(err) => println!("{}: {err}", style(tr().error).bold().red()),
             },
             "h" | "history" => {
-                println!("{}", style("History:").bold());
+                let verbose = matches!(arg, "-v" | "--verbose");
+                println!("{}", style(tr().history_title).bold());
                 for (i, message) in self.messages.iter().enumerate() {
-                    println!("[{}] {} => {}", i, message.role, message.content);
+                    match &message.meta {
+                        Some(meta) if verbose => println!(
+                            "[{}] {} ({}) => {}",
+                            i,
+                            message.role,
+                            style(meta).dim(),
+                            message.content
+                        ),
+                        _ => println!("[{}] {} => {}", i, message.role, message.content),
+                    }
+                }
+            }
+            "checkpoint" => {
+                if arg.is_empty() {
+                    println!("{}", style(tr().checkpoints_title).bold());
+                    for (tag, len) in &self.checkpoints {
+                        println!("  {tag} ({})", (tr().message_count)(*len));
+                    }
+                } else {
+                    self.checkpoints.retain(|(t, _)| t != arg);
+                    self.checkpoints.push((arg.to_owned(), self.messages.len()));
+                    println!("{}", (tr().checkpoint_saved)(arg));
+                }
+            }
+            "tag" => match &mut self.saved_session {
+                Some(saved) => {
+                    saved.add_tags(arg);
+                    println!("{}", (tr().tags)(&saved.tags.join(", ")));
+                    if let Err(err) = self.save_session() {
+                        println!("{}: {err}", style(tr().error).bold().red());
+                    }
+                }
+                None => println!("{}", tr().sessions_not_saved),
+            },
+            "params" => {
+                println!("{}", style(tr().params_title).bold());
+                for (name, value) in self.params() {
+                    println!("  {name:<13} {value}");
                 }
             }
-            _ => {
-                println!("Unknown command: \\{cmd}. Enter '\\?' for help.");
+            "set" => match self.set_param(arg) {
+                Ok(()) => {}
+                Err(err) => println!("{}: {err}", style(tr().error).bold().red()),
+            },
+            "attachments" => {
+                println!("{}", style(tr().attachments_title).bold());
+                for (i, a) in self.attached.iter().chain(&self.attachments).enumerate() {
+                    let message = match a.message {
+                        Some(message) => (tr().attachment_sent)(message),
+                        None => tr().attachment_pending.to_owned(),
+                    };
+                    println!("  [{i}] {} {}", a.name, style(format!("({message})")).dim());
+                }
             }
+            "attach" => match self.reattach(arg) {
+                Ok(name) => println!("{}", (tr().reattached)(&name)),
+                Err(err) => println!("{}: {err}", style(tr().error).bold().red()),
+            },
+            "rollback" => match self.rollback(arg) {
+                Ok(()) => println!("{}", (tr().rolled_back)(arg)),
+                Err(err) => println!("{}: {err}", style(tr().error).bold().red()),
+            },
+            _ => match self.run_script(cmd, arg) {
+                Some(Ok(())) => {}
+                Some(Err(err)) => println!("{}: {err:#}", style(tr().error).bold().red()),
+                None => println!("{}", (tr().unknown_command)(cmd)),
+            },
         }
     }
 
+    /// Run a command defined by a user script, if any, and apply the changes it requests
+    #[cfg(feature = "scripts")]
+    fn run_script(&mut self, cmd: &str, arg: &str) -> Option<Result<()>> {
+        let scripts = self.scripts.as_ref()?;
+        let result = scripts.run_command(cmd, arg, &self.messages, &self.options.model)?;
+        Some(result.and_then(|(output, api)| {
+            if !output.is_empty() {
+                println!("{output}");
+            }
+            if let Some(model) = api.new_model {
+                self.set_param(&format!("model {model}"))?;
+            }
+            self.queued_prompts.extend(api.prompts);
+            Ok(())
+        }))
+    }
+
+    /// Without the `scripts` feature, there are no user-defined commands
+    #[cfg(not(feature = "scripts"))]
+    fn run_script(&mut self, _cmd: &str, _arg: &str) -> Option<Result<()>> {
+        None
+    }
+
+    /// Attach a file listed by `\attachments` again to the next message, by index
+    fn reattach(&mut self, arg: &str) -> Result<String> {
+        let attachment = arg
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| self.attached.iter().chain(&self.attachments).nth(i))
+            .ok_or_else(|| anyhow!((tr().attachment_not_found)(arg)))?;
+        let attachment = Attachment {
+            message: None,
+            ..attachment.clone()
+        };
+        let name = attachment.name.clone();
+        self.attachments.push(attachment);
+        Ok(name)
+    }
+
     /// Retract the last message sent by user, as well as the subsequent messages
     fn retract(&mut self) -> Result<()> {
         let mut count = 0usize;
@@ -436,10 +1992,92 @@ impl Session {
             }
         }
         if count == 0 {
-            bail!("No message to retract");
+            bail!(tr().no_message_to_retract);
         } else {
-            self.messages.truncate(self.messages.len() - count);
+            self.truncate_messages(self.messages.len() - count);
             Ok(())
         }
     }
+
+    /// Effective request parameters, after merging config file and command-line options
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let unset = || "-".to_owned();
+        vec![
+            ("model", self.options.model.clone()),
+            ("stream", self.options.stream.to_string()),
+            (
+                "temperature",
+                self.options
+                    .temperature
+                    .map_or_else(unset, |t| t.to_string()),
+            ),
+            (
+                "top_p",
+                self.options.top_p.map_or_else(unset, |p| p.to_string()),
+            ),
+            ("api_base_url", self.options.api_base_url.clone()),
+        ]
+    }
+
+    /// Change a request parameter for the rest of the session, e.g. `temperature 0.2`.
+    /// Omitting the value of an optional parameter unsets it.
+    fn set_param(&mut self, arg: &str) -> Result<()> {
+        let (name, value) = match arg.split_once(' ') {
+            Some((name, value)) => (name, value.trim()),
+            None => (arg, ""),
+        };
+        self.apply_param(name, value)?;
+        println!("{}", (tr().param_set)(name, value));
+        Ok(())
+    }
+
+    /// Change a request parameter, validating the value. An empty value unsets it.
+    fn apply_param(&mut self, name: &str, value: &str) -> Result<()> {
+        let range = |(min, max): (f64, f64)| -> Result<Option<f64>> {
+            if value.is_empty() {
+                return Ok(None);
+            }
+            match value.parse::<f64>() {
+                Ok(v) if (min..=max).contains(&v) => Ok(Some(v)),
+                _ => bail!((tr().param_out_of_range)(name, min, max)),
+            }
+        };
+        match name {
+            "model" if value.is_empty() => bail!(tr().set_usage),
+            "model" => self.options.model = value.to_owned(),
+            "stream" => {
+                self.options.stream = value
+                    .parse()
+                    .map_err(|_| anyhow!((tr().param_not_bool)(name)))?
+            }
+            "temperature" => {
+                self.options.temperature = range(self.options.provider.temperature_range())?
+            }
+            "top_p" => self.options.top_p = range(self.options.provider.top_p_range())?,
+            "" => bail!(tr().set_usage),
+            _ => bail!((tr().param_not_settable)(name)),
+        }
+        Ok(())
+    }
+
+    /// Revert the conversation to the point tagged by `\checkpoint`
+    fn rollback(&mut self, tag: &str) -> Result<()> {
+        if tag.is_empty() {
+            bail!(tr().checkpoint_tag_required);
+        }
+        let Some(&(_, len)) = self.checkpoints.iter().find(|(t, _)| t == tag) else {
+            bail!((tr().checkpoint_not_found)(tag));
+        };
+        self.truncate_messages(len);
+        Ok(())
+    }
+
+    /// Drop the messages after the first `len` ones, with the checkpoints pointing past them
+    fn truncate_messages(&mut self, len: usize) {
+        self.messages.truncate(len);
+        self.checkpoints.retain(|(_, l)| *l <= len);
+        self.attached.retain(|a| a.message.is_some_and(|i| i < len));
+        // Summarizing again, if needed, includes the messages kept
+        self.summary = self.summary.take().filter(|summary| summary.end <= len);
+    }
 }
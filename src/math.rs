@@ -0,0 +1,595 @@
+use crate::filter;
+
+/// Lines held back at most while waiting for the end of display math
+const MAX_HELD_LINES: usize = 40;
+
+/// Renders LaTeX math in streamed text for the terminal.
+///
+/// Text is passed through as it arrives, except from a possible math delimiter (`$`, `\(` or
+/// `\[`) to the end of its line, or to the end of display math spanning several lines. Math
+/// in code is left alone.
+#[derive(Default)]
+pub struct MathBuffer {
+    /// Command rendering LaTeX given on stdin, instead of the Unicode approximation
+    renderer: Option<String>,
+    /// Text of the current line already passed through
+    line: String,
+    /// Text held back
+    held: String,
+    in_code_block: bool,
+}
+
+impl MathBuffer {
+    pub fn new(renderer: Option<String>) -> Self {
+        Self {
+            renderer,
+            ..Self::default()
+        }
+    }
+
+    /// Add a chunk of text, returning the text to print now
+    pub fn push(&mut self, chunk: &str) -> String {
+        let mut out = String::new();
+        for part in chunk.split_inclusive('\n') {
+            if !self.held.is_empty() {
+                self.held.push_str(part);
+            } else {
+                let start = if self.in_code_block {
+                    None
+                } else {
+                    part.find(['$', '\\'])
+                };
+                let (passed, held) = part.split_at(start.unwrap_or(part.len()));
+                out.push_str(passed);
+                self.line.push_str(passed);
+                self.held.push_str(held);
+            }
+            if !part.ends_with('\n') {
+                continue;
+            }
+            if !self.held.is_empty() {
+                if has_open_display_math(&self.held) && self.held.lines().count() < MAX_HELD_LINES {
+                    continue;
+                }
+                let held = std::mem::take(&mut self.held);
+                self.line.push_str(held.lines().next().unwrap_or_default());
+                out.push_str(&render(&held, self.renderer.as_deref()));
+            }
+            if self.line.trim_start().starts_with("```") {
+                self.in_code_block = !self.in_code_block;
+            }
+            self.line.clear();
+        }
+        out
+    }
+
+    /// End of the text: the text still held back
+    pub fn finish(&mut self) -> String {
+        let held = std::mem::take(&mut self.held);
+        self.line.clear();
+        self.in_code_block = false;
+        render(&held, self.renderer.as_deref())
+    }
+}
+
+/// Whether the text has display math without its end delimiter yet
+fn has_open_display_math(text: &str) -> bool {
+    text.matches("$$").count() % 2 == 1 || text.matches("\\[").count() > text.matches("\\]").count()
+}
+
+/// Replace the math in the text, outside of inline code, by its rendering
+pub fn render(text: &str, renderer: Option<&str>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['$', '\\', '`']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // Inline code is copied as is
+        if rest.starts_with('`') {
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let fence = &rest[..ticks];
+            let end = rest[ticks..]
+                .find(fence)
+                .map_or(rest.len(), |i| i + 2 * ticks);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        match find_math(rest) {
+            Some((len, latex, display)) => {
+                out.push_str(&render_latex(latex, display, renderer));
+                rest = &rest[len..];
+            }
+            None => {
+                let len = rest.chars().next().map_or(0, char::len_utf8);
+                out.push_str(&rest[..len]);
+                rest = &rest[len..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The math starting the text, as the length including delimiters, the LaTeX and whether
+/// it's display math
+fn find_math(text: &str) -> Option<(usize, &str, bool)> {
+    for (open, close, display) in [
+        ("$$", "$$", true),
+        ("\\[", "\\]", true),
+        ("\\(", "\\)", false),
+    ] {
+        if let Some(rest) = text.strip_prefix(open) {
+            let end = rest.find(close)?;
+            return Some((open.len() + end + close.len(), &rest[..end], display));
+        }
+    }
+    // `$x$` with no space inside the delimiters, and no digit after them, unlike `$5 or $10`
+    let rest = text.strip_prefix('$')?;
+    if rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let end = rest.find(['$', '\n'])?;
+    let latex = &rest[..end];
+    let after = &rest[end + 1..];
+    if !rest[end..].starts_with('$')
+        || latex.is_empty()
+        || latex.ends_with(char::is_whitespace)
+        || after.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return None;
+    }
+    Some((end + 2, latex, false))
+}
+
+fn render_latex(latex: &str, display: bool, renderer: Option<&str>) -> String {
+    if let Some(renderer) = renderer {
+        match filter::run_command(renderer, latex) {
+            Ok(rendered) => return rendered.trim_end().to_owned(),
+            Err(err) => log::debug!("Math renderer failed: {err:#}"),
+        }
+    }
+    let rendered = to_unicode(latex.trim(), display);
+    if display {
+        rendered
+            .lines()
+            .map(|line| format!("    {}", line.trim()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        rendered
+    }
+}
+
+/// Approximate LaTeX math with Unicode, e.g. `\frac{1}{2} \alpha^2` as `½ α²`
+pub fn to_unicode(latex: &str, display: bool) -> String {
+    let chars: Vec<char> = latex.chars().collect();
+    let mut converter = Converter {
+        chars: &chars,
+        pos: 0,
+        display,
+    };
+    converter.convert()
+}
+
+struct Converter<'a> {
+    chars: &'a [char],
+    pos: usize,
+    display: bool,
+}
+
+impl Converter<'_> {
+    fn convert(&mut self) -> String {
+        let mut out = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '\\' => out.push_str(&self.command()),
+                '^' => {
+                    let arg = self.arg();
+                    out.push_str(&script(&arg, superscript, '^'));
+                }
+                '_' => {
+                    let arg = self.arg();
+                    out.push_str(&script(&arg, subscript, '_'));
+                }
+                '{' | '}' => {}
+                '~' => out.push(' '),
+                '&' => out.push(' '),
+                '\'' => out.push('′'),
+                c if c.is_whitespace() => {
+                    if !out.ends_with(' ') && !out.is_empty() {
+                        out.push(' ');
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    /// Convert the command after a backslash, with its arguments
+    fn command(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(char::is_ascii_alphabetic)
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            let Some(&c) = self.chars.get(self.pos) else {
+                return "\\".to_owned();
+            };
+            self.pos += 1;
+            return match c {
+                ',' | ':' | ';' | ' ' => " ".to_owned(),
+                '!' => String::new(),
+                '\\' if self.display => "\n".to_owned(),
+                '\\' => "; ".to_owned(),
+                '|' => "‖".to_owned(),
+                c => c.to_string(),
+            };
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.arg();
+                let denominator = self.arg();
+                fraction(&numerator, &denominator)
+            }
+            "sqrt" => {
+                let root = self.optional_arg();
+                let arg = self.arg();
+                let sign = match root.as_deref() {
+                    None | Some("2") => "√".to_owned(),
+                    Some("3") => "∛".to_owned(),
+                    Some("4") => "∜".to_owned(),
+                    Some(n) => format!("{}√", script(n, superscript, '^')),
+                };
+                format!("{sign}{}", group(&arg))
+            }
+            "text" | "textrm" | "textit" | "textbf" | "mathrm" | "mathit" | "mathbf" | "mathsf"
+            | "mathtt" | "operatorname" | "boldsymbol" | "mbox" => self.arg(),
+            "mathbb" => self.arg().chars().map(double_struck).collect(),
+            "hat" | "widehat" => combine(&self.arg(), '\u{0302}'),
+            "bar" | "overline" => combine(&self.arg(), '\u{0305}'),
+            "vec" => combine(&self.arg(), '\u{20D7}'),
+            "dot" => combine(&self.arg(), '\u{0307}'),
+            "ddot" => combine(&self.arg(), '\u{0308}'),
+            "tilde" | "widetilde" => combine(&self.arg(), '\u{0303}'),
+            "begin" | "end" => {
+                self.arg();
+                String::new()
+            }
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "displaystyle" | "limits" => {
+                String::new()
+            }
+            "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan"
+            | "sinh" | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "lim" | "max" | "min"
+            | "sup" | "inf" | "det" | "gcd" | "arg" | "deg" | "dim" | "ker" | "mod" | "bmod"
+            | "Pr" => format!("{name} "),
+            name => symbol(name).map_or_else(|| format!("\\{name}"), str::to_owned),
+        }
+    }
+
+    /// The converted argument of a command, `^` or `_`: a group or a single token
+    fn arg(&mut self) -> String {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        match self.chars.get(self.pos) {
+            Some('{') => {
+                let inner = self.group_source('{', '}');
+                to_unicode(&inner, self.display)
+            }
+            Some('\\') => {
+                self.pos += 1;
+                self.command()
+            }
+            Some(&c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    /// The source of an optional `[...]` argument
+    fn optional_arg(&mut self) -> Option<String> {
+        (self.chars.get(self.pos) == Some(&'[')).then(|| self.group_source('[', ']'))
+    }
+
+    /// The source between balanced delimiters, starting at the opening one
+    fn group_source(&mut self, open: char, close: char) -> String {
+        let start = self.pos + 1;
+        let mut depth = 0;
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return self.chars[start..self.pos - 1].iter().collect();
+                }
+            }
+        }
+        self.chars[start.min(self.chars.len())..].iter().collect()
+    }
+}
+
+/// Wrap in parentheses unless it's a single term
+fn group(text: &str) -> String {
+    if text.chars().count() <= 1 || text.chars().all(char::is_alphanumeric) {
+        text.to_owned()
+    } else {
+        format!("({text})")
+    }
+}
+
+fn fraction(numerator: &str, denominator: &str) -> String {
+    let vulgar = match (numerator, denominator) {
+        ("1", "2") => Some("½"),
+        ("1", "3") => Some("⅓"),
+        ("2", "3") => Some("⅔"),
+        ("1", "4") => Some("¼"),
+        ("3", "4") => Some("¾"),
+        ("1", "5") => Some("⅕"),
+        ("1", "6") => Some("⅙"),
+        ("1", "8") => Some("⅛"),
+        _ => None,
+    };
+    match vulgar {
+        Some(vulgar) => vulgar.to_owned(),
+        None => format!("{}/{}", group(numerator), group(denominator)),
+    }
+}
+
+/// Superscript or subscript the text, falling back to `^(...)` if a character has no form
+fn script(text: &str, map: fn(char) -> Option<char>, marker: char) -> String {
+    match text.chars().map(map).collect::<Option<String>>() {
+        Some(mapped) => mapped,
+        None if text.chars().count() == 1 => format!("{marker}{text}"),
+        None => format!("{marker}({text})"),
+    }
+}
+
+fn combine(text: &str, mark: char) -> String {
+    text.chars().flat_map(|c| [c, mark]).collect()
+}
+
+fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' | '−' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'T' => 'ᵀ',
+        '′' => '′',
+        '*' => '*',
+        _ => return None,
+    })
+}
+
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' | '−' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        _ => return None,
+    })
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'C' => 'ℂ',
+        'H' => 'ℍ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        'E' => '𝔼',
+        '1' => '𝟙',
+        c => c,
+    }
+}
+
+fn symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" | "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "rho" | "varrho" => "ρ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" | "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "times" => "×",
+        "cdot" | "cdotp" => "·",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "•",
+        "oplus" => "⊕",
+        "otimes" => "⊗",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "ll" => "≪",
+        "gg" => "≫",
+        "neq" | "ne" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "infty" => "∞",
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "int" => "∫",
+        "iint" => "∬",
+        "iiint" => "∭",
+        "oint" => "∮",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "to" | "rightarrow" => "→",
+        "gets" | "leftarrow" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "longrightarrow" => "⟶",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "bigcup" => "⋃",
+        "bigcap" => "⋂",
+        "setminus" => "∖",
+        "emptyset" | "varnothing" => "∅",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "neg" | "lnot" => "¬",
+        "land" | "wedge" => "∧",
+        "lor" | "vee" => "∨",
+        "top" => "⊤",
+        "bot" | "perp" => "⊥",
+        "vdash" => "⊢",
+        "models" => "⊨",
+        "cdots" => "⋯",
+        "ldots" | "dots" => "…",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "angle" => "∠",
+        "parallel" => "∥",
+        "mid" => "∣",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "vert" | "lvert" | "rvert" => "|",
+        "Vert" | "lVert" | "rVert" => "‖",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        "prime" => "′",
+        "degree" => "°",
+        "quad" => "  ",
+        "qquad" => "    ",
+        "backslash" => "\\",
+        _ => return None,
+    })
+}
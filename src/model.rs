@@ -1,9 +1,50 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct Message {
     pub role: String,
     pub content: String,
+
+    /// How a response was produced. Local bookkeeping, never sent to the API.
+    #[serde(skip)]
+    pub meta: Option<MessageMeta>,
+}
+
+/// Model and parameters which produced a response
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MessageMeta {
+    pub model: String,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    /// API base URL of the provider
+    pub provider: String,
+    /// Tokens used, if reported by the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ResponseUsage>,
+    /// Whether the response was cut at `--max-output-bytes` or `--max-lines`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl fmt::Display for MessageMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.model)?;
+        if let Some(temperature) = self.temperature {
+            write!(f, ", temperature {temperature}")?;
+        }
+        if let Some(top_p) = self.top_p {
+            write!(f, ", top_p {top_p}")?;
+        }
+        write!(f, ", {}", self.provider)?;
+        if self.truncated {
+            write!(f, ", truncated")?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -23,6 +64,49 @@ pub struct Request {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+
+    /// Images attached to the messages, by index in `messages`, sent in the format of the
+    /// provider by its adapter
+    #[serde(skip)]
+    pub images: BTreeMap<usize, Vec<Image>>,
+}
+
+/// An image attached to a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    /// MIME type, e.g. `image/png`
+    pub mime: String,
+    /// Base64-encoded content
+    pub data: String,
+}
+
+/// Options of streamed responses, e.g. to report the token usage in the last chunk
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+/// Constrain the format of the response, e.g. `{"type": "json_object"}` for JSON mode
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResponseFormat {
+    pub r#type: String,
+}
+
+impl ResponseFormat {
+    pub fn json_object() -> Self {
+        Self {
+            r#type: "json_object".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -42,13 +126,34 @@ pub struct ResponseChoice {
     pub finish_reason: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct ResponseUsage {
     pub completion_tokens: isize,
     pub prompt_tokens: isize,
     pub total_tokens: isize,
 }
 
+impl ResponseUsage {
+    /// Combine usage reported in parts, e.g. the prompt tokens at the start of a stream and
+    /// the completion tokens at its end. Counts reported repeatedly are cumulative.
+    pub fn merge(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                let prompt_tokens = a.prompt_tokens.max(b.prompt_tokens);
+                let completion_tokens = a.completion_tokens.max(b.completion_tokens);
+                Some(Self {
+                    prompt_tokens,
+                    completion_tokens,
+                    total_tokens: (prompt_tokens + completion_tokens)
+                        .max(a.total_tokens)
+                        .max(b.total_tokens),
+                })
+            }
+            (a, b) => a.or(b),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ResponseStreamMessage {
     pub id: String,
@@ -56,6 +161,9 @@ pub struct ResponseStreamMessage {
     pub created: u64,
     pub model: String,
     pub choices: Vec<ResponseDeltaChoice>,
+    /// Only in the last chunk, if requested with `stream_options`
+    #[serde(default)]
+    pub usage: Option<ResponseUsage>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -65,10 +173,24 @@ pub struct ResponseDeltaChoice {
     pub finish_reason: Option<String>,
 }
 
+/// Models offered by the provider, as returned by `GET /models`
+#[derive(Debug, Deserialize)]
+pub struct ModelList {
+    pub data: Vec<ModelInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+}
+
 /// OpenAI API returns error object on failure
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct ApiError {
     pub message: String,
+    /// Not reported by every provider
+    #[serde(default)]
     pub r#type: String,
     pub param: Option<serde_json::Value>,
     pub code: Option<serde_json::Value>,
@@ -0,0 +1,94 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Result};
+use log::debug;
+use sha2::{Digest, Sha256};
+
+use crate::i18n::tr;
+use crate::storage::{atomic_write, cache_dir};
+use crate::Options;
+
+/// How long the model list of a provider is cached
+const MODELS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Check that the provider offers the model, to fail with a suggestion on typos instead of a
+/// 404 from the API. Skipped if the model list can't be fetched.
+pub async fn check(options: &Options) -> Result<()> {
+    let ids = match list(options, false).await {
+        Ok(ids) if ids.contains(&options.model) => return Ok(()),
+        // The cached list may predate the model
+        Ok(_) => list(options, true).await,
+        Err(err) => Err(err),
+    };
+    let ids = match ids {
+        Ok(ids) => ids,
+        Err(err) => {
+            debug!("Skipping the model check: {err:#}");
+            return Ok(());
+        }
+    };
+    if ids.contains(&options.model) {
+        return Ok(());
+    }
+    bail!((tr().unknown_model)(
+        &options.model,
+        closest(&options.model, &ids)
+    ));
+}
+
+/// IDs of the models offered by the provider, cached per provider and API base URL
+async fn list(options: &Options, refresh: bool) -> Result<Vec<String>> {
+    let provider = options.provider;
+    let key = format!(
+        "{:x}",
+        Sha256::digest(format!("{provider:?} {}", options.api_base_url).as_bytes())
+    );
+    let cache_file = cache_dir().join("models").join(&key[..16]);
+    if let Ok(metadata) = std::fs::metadata(&cache_file) {
+        let age = SystemTime::now()
+            .duration_since(metadata.modified()?)
+            .unwrap_or_default();
+        if !refresh && age < MODELS_TTL {
+            return Ok(serde_json::from_str(&std::fs::read_to_string(
+                &cache_file,
+            )?)?);
+        }
+    }
+
+    let body = reqwest::Client::new()
+        .get(provider.models_url(&options.api_base_url))
+        .headers(provider.headers(&options.api_key))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let ids = provider.parse_models(&body)?;
+    atomic_write(&cache_file, serde_json::to_string(&ids)?)?;
+    Ok(ids)
+}
+
+/// The ID closest to the model by edit distance, if it looks like a typo of it
+fn closest<'a>(model: &str, ids: &'a [String]) -> Option<&'a str> {
+    ids.iter()
+        .map(|id| (edit_distance(model, id), id))
+        .filter(|(distance, _)| *distance <= model.chars().count().div_ceil(3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, id)| id.as_str())
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,61 @@
+use std::net::IpAddr;
+
+use anyhow::{bail, Result};
+use reqwest::Url;
+
+use crate::Options;
+
+/// With `--offline`, refuse a URL unless it points to the local machine or a host allowed by
+/// `offline_allow`. `what` names the use of the URL in the error, e.g. "API endpoint".
+pub fn check_url(options: &Options, what: &str, url: &str) -> Result<()> {
+    if !options.offline {
+        return Ok(());
+    }
+    match is_local(options, url) {
+        None => bail!("Offline mode: the {what} {url} isn't a valid URL"),
+        Some(false) => bail!(
+            "Offline mode: the {what} {url} isn't on this machine. Allow its host with \
+            offline_allow in ~/.heygpt.toml"
+        ),
+        Some(true) => Ok(()),
+    }
+}
+
+/// Whether a URL points to the local machine or a host allowed by `offline_allow`, or `None`
+/// if it isn't a valid URL
+pub fn is_local(options: &Options, url: &str) -> Option<bool> {
+    let (host, port) = Url::parse(url)
+        .ok()
+        .and_then(|url| Some((url.host_str()?.to_owned(), url.port_or_known_default())))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let is_local = host == "localhost"
+        || host.ends_with(".localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    let is_allowed = options.offline_allow.iter().any(|allowed| {
+        allowed == host || port.is_some_and(|port| *allowed == format!("{host}:{port}"))
+    });
+    Some(is_local || is_allowed)
+}
+
+/// With `--offline`, refuse a git remote on another host. Local paths are always allowed.
+pub fn check_git_remote(options: &Options, remote: &str) -> Result<()> {
+    if remote.contains("://") {
+        return check_url(options, "git remote", remote);
+    }
+    // scp-like syntax, e.g. `git@github.com:me/data.git`, unless it's a path
+    match remote.split_once(':') {
+        Some((host, _)) if !host.contains('/') => {
+            let host = host.rsplit('@').next().unwrap_or(host);
+            check_url(options, "git remote", &format!("ssh://{host}"))
+                .map_err(|_| offline_remote_error(remote))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn offline_remote_error(remote: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Offline mode: the git remote {remote} isn't on this machine. Use a local path, or \
+        allow its host with offline_allow in ~/.heygpt.toml"
+    )
+}
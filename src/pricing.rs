@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use log::debug;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::model::ResponseUsage;
+use crate::storage::{atomic_write, cache_dir};
+
+/// The pricing table shipped with heygpt, also published at `PRICING_URL`
+const BUILTIN: &str = include_str!("../pricing.json");
+
+/// Where `heygpt pricing update` downloads the pricing table from by default
+pub const PRICING_URL: &str = "https://raw.githubusercontent.com/fuyufjh/heygpt/main/pricing.json";
+
+/// Price of a model in USD per million tokens
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Prices by model name prefix, the longest matching prefix applying
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Table {
+    pub models: BTreeMap<String, Price>,
+}
+
+impl Table {
+    pub fn parse(json: &str) -> Result<Self> {
+        let table: Self = serde_json::from_str(json)?;
+        if table.models.is_empty() {
+            bail!("The pricing table has no models");
+        }
+        if let Some((model, _)) = table
+            .models
+            .iter()
+            .find(|(_, p)| !(p.input >= 0.0 && p.output >= 0.0))
+        {
+            bail!("Invalid price of {model}");
+        }
+        Ok(table)
+    }
+}
+
+/// The pricing table in use: the one downloaded last, or the built-in one
+pub fn table() -> &'static Table {
+    static TABLE: OnceLock<Table> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let downloaded = std::fs::read_to_string(table_path())
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Table::parse(&json));
+        match downloaded {
+            Ok(table) => table,
+            Err(err) => {
+                debug!("Using the built-in pricing table: {err:#}");
+                Table::parse(BUILTIN).expect("invalid built-in pricing table")
+            }
+        }
+    })
+}
+
+/// Where the pricing table downloaded by `heygpt pricing update` is stored
+pub fn table_path() -> PathBuf {
+    cache_dir().join("pricing.json")
+}
+
+/// Download the pricing table and store it for the next invocations, returning it
+pub async fn update(url: &str) -> Result<Table> {
+    let json = reqwest::get(url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to download {url}"))?
+        .text()
+        .await?;
+    let table = Table::parse(&json).with_context(|| format!("Invalid pricing table at {url}"))?;
+    atomic_write(&table_path(), serde_json::to_string_pretty(&table)?)?;
+    Ok(table)
+}
+
+/// The price of the model from the pricing table and `overrides`, e.g. `[pricing]` of the
+/// config file or a profile, which take precedence for the same prefix
+pub fn price(overrides: &BTreeMap<String, Price>, model: &str) -> Option<Price> {
+    let table = &table().models;
+    overrides
+        .iter()
+        .chain(
+            table
+                .iter()
+                .filter(|(prefix, _)| !overrides.contains_key(*prefix)),
+        )
+        .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, price)| *price)
+}
+
+/// Estimated cost of a completion in USD, if the price of the model is known
+pub fn cost(
+    overrides: &BTreeMap<String, Price>,
+    model: &str,
+    usage: &ResponseUsage,
+) -> Option<f64> {
+    let price = price(overrides, model)?;
+    Some(
+        (usage.prompt_tokens as f64 * price.input + usage.completion_tokens as f64 * price.output)
+            / 1e6,
+    )
+}
@@ -0,0 +1,102 @@
+use anyhow::{bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::{is_yes, tr};
+use crate::{offline, Options};
+
+/// How private conversations are, from `privacy` in the config file or a profile, from the
+/// least to the most strict
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Privacy {
+    /// No restrictions
+    #[default]
+    CloudOk,
+    /// Nothing of the conversations is written to disk, and sending local files to a remote
+    /// API needs confirmation
+    NoLog,
+    /// Like `no_log`, and only APIs on this machine or allowed by `offline_allow` are used
+    LocalOnly,
+}
+
+impl Privacy {
+    /// Whether conversations may be written to disk: saved sessions, the last conversation,
+    /// the input history, the audit log, the transcript and quiz results
+    pub fn allows_logs(self) -> bool {
+        self == Self::CloudOk
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::CloudOk => "cloud_ok",
+            Self::NoLog => "no_log",
+            Self::LocalOnly => "local_only",
+        }
+    }
+}
+
+/// Apply the privacy level to the options, failing if they conflict with it
+pub fn enforce(options: &mut Options) -> Result<()> {
+    let privacy = options.privacy;
+    if privacy.allows_logs() {
+        return Ok(());
+    }
+    options.save_sessions = false;
+    if options.audit_log.is_some() {
+        bail!(
+            "The privacy level {} conflicts with audit_log, which writes the conversations to \
+            disk",
+            privacy.name()
+        );
+    }
+    if options.tee.is_some() {
+        bail!(
+            "The privacy level {} conflicts with --tee, which writes the conversation to disk",
+            privacy.name()
+        );
+    }
+    if privacy == Privacy::LocalOnly
+        && offline::is_local(options, &options.api_base_url) != Some(true)
+    {
+        bail!(
+            "The privacy level local_only only allows APIs on this machine, not {}. Allow a \
+            host on the local network with offline_allow in ~/.heygpt.toml",
+            options.api_base_url
+        );
+    }
+    Ok(())
+}
+
+/// Under a strict privacy level, ask before sending local files to an API on another machine.
+/// Fails without a terminal to ask on.
+pub fn confirm_files(options: &Options, files: &[String]) -> Result<()> {
+    let local: Vec<&str> = files
+        .iter()
+        .map(String::as_str)
+        .filter(|file| !file.starts_with("http://") && !file.starts_with("https://"))
+        .collect();
+    if options.privacy.allows_logs()
+        || local.is_empty()
+        || offline::is_local(options, &options.api_base_url) == Some(true)
+    {
+        return Ok(());
+    }
+    let question = (tr().confirm_files)(&local.join(", "), &options.api_base_url);
+    let term = console::Term::stderr();
+    if !term.is_term() || !atty::is(atty::Stream::Stdin) {
+        bail!(
+            "{question} The privacy level {} requires confirming it, but there is no terminal \
+            to ask on",
+            options.privacy.name()
+        );
+    }
+    term.write_str(&format!("{question} [y/N] "))?;
+    let answer = term.read_line()?;
+    if !is_yes(&answer) {
+        bail!(tr().files_not_sent);
+    }
+    Ok(())
+}
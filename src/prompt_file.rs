@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{ClapSerde, Options};
+
+/// Options set by the YAML front matter of a prompt file
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct FrontMatter {
+    model: Option<String>,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    system: Option<String>,
+    /// Files or URLs to attach, relative to the prompt file
+    files: Vec<String>,
+}
+
+/// A prompt read from a Markdown file with `-p`, e.g.
+///
+/// ```markdown
+/// ---
+/// model: gpt-4o
+/// temperature: 0.2
+/// system: You are a senior Rust reviewer.
+/// files: [../src/lib.rs]
+/// ---
+/// Review this code for soundness issues.
+/// ```
+#[derive(Debug)]
+pub struct PromptFile {
+    front_matter: FrontMatter,
+    body: String,
+}
+
+impl PromptFile {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the prompt file {path}"))?;
+        let mut prompt = Self::parse(&content)
+            .with_context(|| format!("Invalid front matter in the prompt file {path}"))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        for file in &mut prompt.front_matter.files {
+            let is_url = file.starts_with("http://") || file.starts_with("https://");
+            if !is_url {
+                *file = dir.join(&*file).to_string_lossy().into_owned();
+            }
+        }
+        Ok(prompt)
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        let mut lines = content.split_inclusive('\n');
+        if lines.next().map(str::trim_end) != Some("---") {
+            return Ok(Self {
+                front_matter: FrontMatter::default(),
+                body: content.trim().to_owned(),
+            });
+        }
+        let mut yaml = String::new();
+        let mut body = String::new();
+        let mut closed = false;
+        for line in lines {
+            if closed {
+                body.push_str(line);
+            } else if matches!(line.trim_end(), "---" | "...") {
+                closed = true;
+            } else {
+                yaml.push_str(line);
+            }
+        }
+        anyhow::ensure!(closed, "The front matter isn't closed with `---`");
+        let front_matter = if yaml.trim().is_empty() {
+            FrontMatter::default()
+        } else {
+            #[cfg(feature = "yaml")]
+            {
+                serde_yaml::from_str(&yaml)?
+            }
+            #[cfg(not(feature = "yaml"))]
+            anyhow::bail!("heygpt was built without the `yaml` feature, needed for front matter")
+        };
+        Ok(Self {
+            front_matter,
+            body: body.trim().to_owned(),
+        })
+    }
+
+    /// Apply the prompt file between the config file and the command line: the front matter
+    /// overrides `options`, and is overridden by the options given in `cli`. The body becomes
+    /// the prompt, followed by the prompt given in `cli`, and the attached files come first.
+    pub fn apply(self, options: &mut Options, cli: &mut <Options as ClapSerde>::Opt) {
+        let front_matter = self.front_matter;
+        if let Some(model) = front_matter.model {
+            options.model = model;
+        }
+        if front_matter.temperature.is_some() {
+            options.temperature = front_matter.temperature;
+        }
+        if front_matter.top_p.is_some() {
+            options.top_p = front_matter.top_p;
+        }
+        if front_matter.system.is_some() {
+            options.system = front_matter.system;
+        }
+        if !front_matter.files.is_empty() {
+            let mut files = front_matter.files;
+            files.extend(cli.files.take().unwrap_or_default());
+            cli.files = Some(files);
+        }
+        if !self.body.is_empty() {
+            let mut prompt = self.body;
+            let words = cli.prompt.take().unwrap_or_default();
+            if !words.is_empty() {
+                prompt.push_str("\n\n");
+                prompt.push_str(&words.join(" "));
+            }
+            cli.prompt = Some(vec![prompt]);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use anyhow::Result;
+use reqwest::header::HeaderMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::model::{Image, Request, ResponseUsage};
+
+mod anthropic;
+mod gemini;
+mod ollama;
+pub mod openai;
+
+/// API of the provider, which determines the format of requests and responses
+#[derive(
+    clap::ValueEnum, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// OpenAI and compatible APIs, e.g. Azure OpenAI, Groq or llama.cpp
+    #[default]
+    Openai,
+    /// Anthropic Messages API
+    Anthropic,
+    /// Google Gemini API
+    Gemini,
+    /// Native API of Ollama
+    Ollama,
+}
+
+/// A complete response, as parsed by the adapter of the provider
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reply {
+    pub content: String,
+    /// Model reported by the API, if any
+    pub model: Option<String>,
+    pub usage: Option<ResponseUsage>,
+}
+
+/// An event of a streamed response, as parsed by the adapter of the provider
+#[derive(Debug, Default, PartialEq)]
+pub struct Chunk {
+    pub role: Option<String>,
+    pub content: Option<String>,
+    pub model: Option<String>,
+    /// Tokens used so far, merged with the previous reports by `ResponseUsage::merge`
+    pub usage: Option<ResponseUsage>,
+    /// Whether this is the last event of the response
+    pub done: bool,
+}
+
+impl Provider {
+    /// Base URL of the provider's API, used unless `api_base_url` is set
+    pub fn default_base_url(self) -> &'static str {
+        match self {
+            Self::Openai => openai::DEFAULT_BASE_URL,
+            Self::Anthropic => anthropic::DEFAULT_BASE_URL,
+            Self::Gemini => gemini::DEFAULT_BASE_URL,
+            Self::Ollama => ollama::DEFAULT_BASE_URL,
+        }
+    }
+
+    /// Environment variable holding the API key
+    pub fn api_key_env(self) -> Option<&'static str> {
+        match self {
+            Self::Openai => Some("OPENAI_API_KEY"),
+            Self::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Self::Gemini => Some("GEMINI_API_KEY"),
+            Self::Ollama => None,
+        }
+    }
+
+    /// Environment variable holding the API base URL
+    pub fn base_url_env(self) -> Option<&'static str> {
+        match self {
+            Self::Openai => Some("OPENAI_API_BASE"),
+            Self::Anthropic => Some("ANTHROPIC_BASE_URL"),
+            Self::Gemini | Self::Ollama => None,
+        }
+    }
+
+    /// Range of `temperature` accepted by the API
+    pub fn temperature_range(self) -> (f64, f64) {
+        match self {
+            Self::Anthropic => (0.0, 1.0),
+            // Ollama accepts any value, but models degrade into noise beyond this
+            Self::Openai | Self::Gemini | Self::Ollama => (0.0, 2.0),
+        }
+    }
+
+    /// Range of `top_p` accepted by the API
+    pub fn top_p_range(self) -> (f64, f64) {
+        (0.0, 1.0)
+    }
+
+    pub fn needs_api_key(self) -> bool {
+        self != Self::Ollama
+    }
+
+    /// URL of the chat endpoint
+    pub fn url(self, base_url: &str, model: &str, stream: bool) -> String {
+        match self {
+            Self::Openai => format!("{base_url}/chat/completions"),
+            Self::Anthropic => format!("{base_url}/messages"),
+            Self::Gemini if stream => {
+                format!("{base_url}/models/{model}:streamGenerateContent?alt=sse")
+            }
+            Self::Gemini => format!("{base_url}/models/{model}:generateContent"),
+            Self::Ollama => format!("{base_url}/api/chat"),
+        }
+    }
+
+    /// URL of the endpoint listing the models
+    pub fn models_url(self, base_url: &str) -> String {
+        match self {
+            Self::Openai | Self::Anthropic | Self::Gemini => format!("{base_url}/models"),
+            Self::Ollama => format!("{base_url}/api/tags"),
+        }
+    }
+
+    pub fn headers(self, api_key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let mut insert = |name: &'static str, value: String| {
+            if let Ok(value) = value.parse() {
+                headers.insert(name, value);
+            }
+        };
+        match self {
+            Self::Openai => insert("authorization", format!("Bearer {api_key}")),
+            Self::Anthropic => {
+                insert("x-api-key", api_key.to_owned());
+                insert("anthropic-version", anthropic::API_VERSION.to_owned());
+            }
+            Self::Gemini => insert("x-goog-api-key", api_key.to_owned()),
+            // Ollama needs no key, but a proxy in front of it may
+            Self::Ollama if !api_key.is_empty() => {
+                insert("authorization", format!("Bearer {api_key}"))
+            }
+            Self::Ollama => {}
+        }
+        headers
+    }
+
+    /// The request body in the format of the provider
+    pub fn body(self, request: &Request) -> Value {
+        match self {
+            Self::Openai => openai::body(request),
+            Self::Anthropic => anthropic::body(request),
+            Self::Gemini => gemini::body(request),
+            Self::Ollama => ollama::body(request),
+        }
+    }
+
+    /// Parse the IDs of the models listed by the models endpoint
+    pub fn parse_models(self, body: &str) -> Result<Vec<String>> {
+        match self {
+            // Anthropic lists the models in the format of OpenAI
+            Self::Openai | Self::Anthropic => openai::parse_models(body),
+            Self::Gemini => gemini::parse_models(body),
+            Self::Ollama => ollama::parse_models(body),
+        }
+    }
+
+    /// Parse the body of a response which isn't streamed
+    pub fn parse_reply(self, body: &str) -> Result<Reply> {
+        match self {
+            Self::Openai => openai::parse_reply(body),
+            Self::Anthropic => anthropic::parse_reply(body),
+            Self::Gemini => gemini::parse_reply(body),
+            Self::Ollama => ollama::parse_reply(body),
+        }
+    }
+
+    /// Parse the data of an event of a streamed response
+    pub fn parse_chunk(self, data: &str) -> Result<Chunk> {
+        match self {
+            Self::Openai => openai::parse_chunk(data),
+            Self::Anthropic => anthropic::parse_chunk(data),
+            Self::Gemini => gemini::parse_chunk(data),
+            Self::Ollama => ollama::parse_chunk(data),
+        }
+    }
+}
+
+/// Splits the body of a streamed response into the data of its events: server-sent events,
+/// or JSON lines for Ollama
+pub struct Decoder {
+    json_lines: bool,
+    buf: Vec<u8>,
+    /// Data of the server-sent event being read
+    data: Option<String>,
+}
+
+impl Decoder {
+    pub fn new(provider: Provider) -> Self {
+        Self {
+            json_lines: provider == Provider::Ollama,
+            buf: Vec::new(),
+            data: None,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The data of the next complete event, if any
+    pub fn next_event(&mut self) -> Option<String> {
+        while let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if self.json_lines {
+                if !line.trim().is_empty() {
+                    return Some(line.to_owned());
+                }
+            } else if line.is_empty() {
+                if let Some(data) = self.data.take() {
+                    return Some(data);
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                match &mut self.data {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => self.data = Some(value.to_owned()),
+                }
+            }
+            // Other fields, such as `event:`, and comments are redundant with the data
+        }
+        None
+    }
+
+    /// The data of an event left at the end of the body without a terminating blank line
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = String::from_utf8_lossy(&std::mem::take(&mut self.buf)).into_owned();
+        if self.json_lines {
+            return Some(rest).filter(|line| !line.trim().is_empty());
+        }
+        if let Some(value) = rest.trim_end().strip_prefix("data:") {
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            self.data = Some(match self.data.take() {
+                Some(data) => format!("{data}\n{value}"),
+                None => value.to_owned(),
+            });
+        }
+        self.data.take()
+    }
+}
+
+/// Role, content and images of the messages other than system prompts, with consecutive
+/// messages of the same role joined, for APIs requiring alternating roles
+fn merge_roles(request: &Request) -> Vec<(&str, String, Vec<&Image>)> {
+    let mut merged: Vec<(&str, String, Vec<&Image>)> = Vec::new();
+    for (i, message) in request.messages.iter().enumerate() {
+        if message.role == "system" {
+            continue;
+        }
+        let images = request.images.get(&i).into_iter().flatten();
+        match merged.last_mut() {
+            Some((role, content, attached)) if *role == message.role => {
+                content.push_str("\n\n");
+                content.push_str(&message.content);
+                attached.extend(images);
+            }
+            _ => merged.push((&message.role, message.content.clone(), images.collect())),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::ValueEnum;
+    use serde_json::json;
+
+    use super::*;
+    use crate::model::{Message, ResponseFormat};
+
+    fn fixture(provider: Provider, name: &str) -> String {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/providers")
+            .join(format!("{provider:?}").to_lowercase())
+            .join(name);
+        std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+    }
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_owned(),
+            content: content.to_owned(),
+            meta: None,
+        }
+    }
+
+    /// A request using every field of `Request`
+    fn request() -> Request {
+        Request {
+            model: "test-model".to_owned(),
+            messages: vec![
+                message("system", "You are terse."),
+                message("user", "Hi"),
+                message("assistant", "Hello."),
+                message("user", "What is 2+2?"),
+                message("user", "Answer in JSON."),
+            ],
+            stream: true,
+            temperature: Some(0.5),
+            top_p: Some(0.9),
+            max_tokens: Some(300),
+            response_format: Some(ResponseFormat::json_object()),
+            stream_options: None,
+            images: [(
+                3,
+                vec![Image {
+                    mime: "image/png".to_owned(),
+                    data: "iVBORw0KGgo=".to_owned(),
+                }],
+            )]
+            .into(),
+        }
+    }
+
+    #[test]
+    fn request_bodies_match_fixtures() {
+        for &provider in Provider::value_variants() {
+            let expected: Value = serde_json::from_str(&fixture(provider, "request.json")).unwrap();
+            assert_eq!(provider.body(&request()), expected, "{provider:?}");
+        }
+    }
+
+    #[test]
+    fn replies_match_fixtures() {
+        for &provider in Provider::value_variants() {
+            let reply = provider
+                .parse_reply(&fixture(provider, "response.json"))
+                .unwrap();
+            let expected: Reply =
+                serde_json::from_str(&fixture(provider, "response.expected.json")).unwrap();
+            assert_eq!(reply, expected, "{provider:?}");
+        }
+    }
+
+    #[test]
+    fn model_lists_match_fixtures() {
+        for &provider in Provider::value_variants() {
+            let models = provider
+                .parse_models(&fixture(provider, "models.json"))
+                .unwrap();
+            let expected: Vec<String> =
+                serde_json::from_str(&fixture(provider, "models.expected.json")).unwrap();
+            assert_eq!(models, expected, "{provider:?}");
+        }
+    }
+
+    #[test]
+    fn streams_match_fixtures() {
+        for &provider in Provider::value_variants() {
+            let stream = fixture(provider, "stream.txt");
+            // Small pieces, to split events and UTF-8 sequences
+            let mut decoder = Decoder::new(provider);
+            let mut events = Vec::new();
+            for piece in stream.as_bytes().chunks(7) {
+                decoder.push(piece);
+                events.extend(std::iter::from_fn(|| decoder.next_event()));
+            }
+            events.extend(decoder.finish());
+
+            let mut role = None;
+            let mut reply = Reply::default();
+            let mut done = false;
+            for event in events {
+                assert!(!done, "{provider:?}: event after the last one");
+                let chunk = provider.parse_chunk(&event).unwrap();
+                role = role.or(chunk.role);
+                reply.model = reply.model.or(chunk.model);
+                reply.content.push_str(&chunk.content.unwrap_or_default());
+                reply.usage = ResponseUsage::merge(reply.usage, chunk.usage);
+                done = chunk.done;
+            }
+            let expected: Value =
+                serde_json::from_str(&fixture(provider, "stream.expected.json")).unwrap();
+            let actual = json!({
+                "role": role,
+                "content": reply.content,
+                "model": reply.model,
+                "usage": reply.usage,
+                "done": done,
+            });
+            assert_eq!(actual, expected, "{provider:?}");
+        }
+    }
+}
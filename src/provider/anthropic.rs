@@ -0,0 +1,112 @@
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+
+use super::{merge_roles, Chunk, Reply};
+use crate::model::{Request, ResponseUsage};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+
+/// Version of the Messages API sent in the `anthropic-version` header
+pub const API_VERSION: &str = "2023-06-01";
+
+/// The API requires a token limit
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+pub fn body(request: &Request) -> Value {
+    let system: Vec<&str> = request
+        .messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+    let messages: Vec<Value> = merge_roles(request)
+        .into_iter()
+        .map(|(role, content, images)| {
+            if images.is_empty() {
+                return json!({"role": role, "content": content});
+            }
+            // Images are best placed before the text referring to them
+            let mut blocks: Vec<Value> = images
+                .into_iter()
+                .map(|image| {
+                    json!({
+                        "type": "image",
+                        "source": {"type": "base64", "media_type": image.mime, "data": image.data},
+                    })
+                })
+                .collect();
+            blocks.push(json!({"type": "text", "text": content}));
+            json!({"role": role, "content": blocks})
+        })
+        .collect();
+
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "stream": request.stream,
+    });
+    if !system.is_empty() {
+        body["system"] = json!(system.join("\n\n"));
+    }
+    if let Some(temperature) = request.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = request.top_p {
+        body["top_p"] = json!(top_p);
+    }
+    // There's no JSON mode, the prompt asks for JSON anyway
+    body
+}
+
+fn usage(usage: &Value) -> Option<ResponseUsage> {
+    let input = usage["input_tokens"].as_i64().unwrap_or(0) as isize;
+    let output = usage["output_tokens"].as_i64().unwrap_or(0) as isize;
+    usage.is_object().then_some(ResponseUsage {
+        prompt_tokens: input,
+        completion_tokens: output,
+        total_tokens: input + output,
+    })
+}
+
+pub fn parse_reply(body: &str) -> Result<Reply> {
+    let response: Value = serde_json::from_str(body)?;
+    let Some(blocks) = response["content"].as_array() else {
+        bail!("Unexpected response: {body}");
+    };
+    let content = blocks
+        .iter()
+        .filter_map(|block| block["text"].as_str())
+        .collect();
+    Ok(Reply {
+        content,
+        model: response["model"].as_str().map(str::to_owned),
+        usage: usage(&response["usage"]),
+    })
+}
+
+pub fn parse_chunk(data: &str) -> Result<Chunk> {
+    let event: Value = serde_json::from_str(data)?;
+    let mut chunk = Chunk::default();
+    match event["type"].as_str().unwrap_or_default() {
+        "message_start" => {
+            let message = &event["message"];
+            chunk.role = message["role"].as_str().map(str::to_owned);
+            chunk.model = message["model"].as_str().map(str::to_owned);
+            chunk.usage = usage(&message["usage"]);
+        }
+        "content_block_delta" => {
+            chunk.content = event["delta"]["text"].as_str().map(str::to_owned);
+        }
+        "message_delta" => chunk.usage = usage(&event["usage"]),
+        "message_stop" => chunk.done = true,
+        "error" => bail!(
+            "{}: {}",
+            event["error"]["type"].as_str().unwrap_or("error"),
+            event["error"]["message"].as_str().unwrap_or(data)
+        ),
+        // `ping`, `content_block_start` and `content_block_stop`
+        _ => {}
+    }
+    Ok(chunk)
+}
@@ -0,0 +1,107 @@
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+
+use super::{merge_roles, Chunk, Reply};
+use crate::model::{Request, ResponseUsage};
+
+pub const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+pub fn body(request: &Request) -> Value {
+    let system: Vec<&str> = request
+        .messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+    let contents: Vec<Value> =
+        merge_roles(request)
+            .into_iter()
+            .map(|(role, content, images)| {
+                let role = if role == "assistant" { "model" } else { "user" };
+                let mut parts = vec![json!({"text": content})];
+                parts.extend(images.into_iter().map(
+                    |image| json!({"inlineData": {"mimeType": image.mime, "data": image.data}}),
+                ));
+                json!({"role": role, "parts": parts})
+            })
+            .collect();
+
+    let mut config = json!({});
+    if let Some(temperature) = request.temperature {
+        config["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = request.top_p {
+        config["topP"] = json!(top_p);
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        config["maxOutputTokens"] = json!(max_tokens);
+    }
+    if request.response_format.is_some() {
+        config["responseMimeType"] = json!("application/json");
+    }
+
+    let mut body = json!({"contents": contents, "generationConfig": config});
+    if !system.is_empty() {
+        body["systemInstruction"] = json!({"parts": [{"text": system.join("\n\n")}]});
+    }
+    body
+}
+
+/// Models are named `models/gemini-...`, and requested without the prefix
+pub fn parse_models(body: &str) -> Result<Vec<String>> {
+    let response: Value = serde_json::from_str(body)?;
+    let Some(models) = response["models"].as_array() else {
+        bail!("Unexpected model list: {body}");
+    };
+    Ok(models
+        .iter()
+        .filter_map(|m| m["name"].as_str())
+        .map(|name| name.strip_prefix("models/").unwrap_or(name).to_owned())
+        .collect())
+}
+
+/// Text, model and usage of a response, or of an event of a streamed response
+fn parse(response: &Value) -> Reply {
+    let content = response["candidates"][0]["content"]["parts"]
+        .as_array()
+        .map(|parts| parts.iter().filter_map(|p| p["text"].as_str()).collect())
+        .unwrap_or_default();
+    let metadata = &response["usageMetadata"];
+    let usage = metadata.is_object().then(|| {
+        let prompt = metadata["promptTokenCount"].as_i64().unwrap_or(0) as isize;
+        let completion = metadata["candidatesTokenCount"].as_i64().unwrap_or(0) as isize;
+        ResponseUsage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            total_tokens: metadata["totalTokenCount"]
+                .as_i64()
+                .map_or(prompt + completion, |t| t as isize),
+        }
+    });
+    Reply {
+        content,
+        model: response["modelVersion"].as_str().map(str::to_owned),
+        usage,
+    }
+}
+
+pub fn parse_reply(body: &str) -> Result<Reply> {
+    let response: Value = serde_json::from_str(body)?;
+    if response["candidates"].as_array().is_none() {
+        bail!("Unexpected response: {body}");
+    }
+    Ok(parse(&response))
+}
+
+/// The stream has no end marker, it ends with the body
+pub fn parse_chunk(data: &str) -> Result<Chunk> {
+    let response: Value = serde_json::from_str(data)?;
+    let reply = parse(&response);
+    Ok(Chunk {
+        role: Some("assistant".to_owned()),
+        content: Some(reply.content),
+        model: reply.model,
+        usage: reply.usage,
+        done: false,
+    })
+}
@@ -0,0 +1,88 @@
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+
+use super::{Chunk, Reply};
+use crate::model::{Request, ResponseUsage};
+
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+pub fn body(request: &Request) -> Value {
+    let mut options = json!({});
+    if let Some(temperature) = request.temperature {
+        options["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = request.top_p {
+        options["top_p"] = json!(top_p);
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        options["num_predict"] = json!(max_tokens);
+    }
+    let mut body = json!({
+        "model": request.model,
+        "messages": request.messages,
+        "stream": request.stream,
+        "options": options,
+    });
+    if request.response_format.is_some() {
+        body["format"] = json!("json");
+    }
+    for (&i, images) in &request.images {
+        let images: Vec<&str> = images.iter().map(|image| image.data.as_str()).collect();
+        body["messages"][i]["images"] = json!(images);
+    }
+    body
+}
+
+/// Local models are named with their tag, which defaults to `latest` in requests
+pub fn parse_models(body: &str) -> Result<Vec<String>> {
+    let response: Value = serde_json::from_str(body)?;
+    let Some(models) = response["models"].as_array() else {
+        bail!("Unexpected model list: {body}");
+    };
+    let mut ids = Vec::new();
+    for name in models.iter().filter_map(|m| m["name"].as_str()) {
+        ids.push(name.to_owned());
+        if let Some(untagged) = name.strip_suffix(":latest") {
+            ids.push(untagged.to_owned());
+        }
+    }
+    Ok(ids)
+}
+
+/// Text, model and usage of a response, or of a line of a streamed response
+fn parse(response: &Value) -> Result<Chunk> {
+    if let Some(error) = response["error"].as_str() {
+        bail!("{error}");
+    }
+    let done = response["done"].as_bool().unwrap_or(false);
+    // Only the last line has the token counts
+    let usage = done.then(|| {
+        let prompt = response["prompt_eval_count"].as_i64().unwrap_or(0) as isize;
+        let completion = response["eval_count"].as_i64().unwrap_or(0) as isize;
+        ResponseUsage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            total_tokens: prompt + completion,
+        }
+    });
+    Ok(Chunk {
+        role: response["message"]["role"].as_str().map(str::to_owned),
+        content: response["message"]["content"].as_str().map(str::to_owned),
+        model: response["model"].as_str().map(str::to_owned),
+        usage,
+        done,
+    })
+}
+
+pub fn parse_reply(body: &str) -> Result<Reply> {
+    let chunk = parse(&serde_json::from_str(body)?)?;
+    Ok(Reply {
+        content: chunk.content.unwrap_or_default(),
+        model: chunk.model,
+        usage: chunk.usage,
+    })
+}
+
+pub fn parse_chunk(data: &str) -> Result<Chunk> {
+    parse(&serde_json::from_str(data)?)
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{Chunk, Reply};
+use crate::model::{ModelList, Request, ResponseMessage, ResponseStreamMessage};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+pub fn body(request: &Request) -> Value {
+    let mut body = serde_json::to_value(request).unwrap();
+    // Messages with images have a list of content parts instead of a string
+    for (&i, images) in &request.images {
+        let message = &mut body["messages"][i];
+        let mut parts = vec![json!({"type": "text", "text": message["content"].take()})];
+        parts.extend(images.iter().map(|image| {
+            json!({
+                "type": "image_url",
+                "image_url": {"url": format!("data:{};base64,{}", image.mime, image.data)},
+            })
+        }));
+        message["content"] = json!(parts);
+    }
+    body
+}
+
+pub fn parse_models(body: &str) -> Result<Vec<String>> {
+    let list: ModelList = serde_json::from_str(body)?;
+    Ok(list.data.into_iter().map(|m| m.id).collect())
+}
+
+pub fn parse_reply(body: &str) -> Result<Reply> {
+    let response: ResponseMessage = serde_json::from_str(body)?;
+    let content = response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content)
+        .unwrap_or_default();
+    Ok(Reply {
+        content,
+        model: Some(response.model),
+        usage: Some(response.usage),
+    })
+}
+
+pub fn parse_chunk(data: &str) -> Result<Chunk> {
+    if data == "[DONE]" {
+        return Ok(Chunk {
+            done: true,
+            ..Chunk::default()
+        });
+    }
+    let message: ResponseStreamMessage = serde_json::from_str(data)?;
+    // The chunk with the usage has no choices
+    let delta = message
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.delta);
+    Ok(Chunk {
+        role: delta.as_ref().and_then(|delta| delta.role.clone()),
+        content: delta.and_then(|delta| delta.content),
+        model: Some(message.model),
+        usage: message.usage,
+        done: false,
+    })
+}
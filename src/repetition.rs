@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Length in words of the sequences which are counted
+const NGRAM: usize = 8;
+
+/// Detects a streamed response looping over the same text, e.g. a local model getting stuck,
+/// by counting how often each sequence of words appears.
+pub struct RepetitionGuard {
+    limit: usize,
+    words: Vec<String>,
+    /// Start of the last word, which may continue in the next chunk
+    partial: String,
+    counts: HashMap<u64, usize>,
+}
+
+impl RepetitionGuard {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            words: Vec::new(),
+            partial: String::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Add a chunk of the response, returning whether a sequence reached the limit
+    pub fn push(&mut self, chunk: &str) -> bool {
+        self.partial.push_str(chunk);
+        let Some(end) = self.partial.rfind(char::is_whitespace) else {
+            return false;
+        };
+        let rest = self.partial.split_off(end);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        let mut looping = false;
+        for word in complete.split_whitespace() {
+            self.words.push(word.to_owned());
+            if self.words.len() >= NGRAM {
+                let mut hasher = DefaultHasher::new();
+                self.words[self.words.len() - NGRAM..].hash(&mut hasher);
+                let count = self.counts.entry(hasher.finish()).or_default();
+                *count += 1;
+                looping |= *count >= self.limit;
+            }
+        }
+        looping
+    }
+}
@@ -0,0 +1,203 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+
+use crate::model::Message;
+
+/// User scripts in Rhai, from `~/.config/heygpt/scripts` on Linux.
+///
+/// A script `NAME.rhai` defining `fn command(args)` adds the command `\NAME` to interactive
+/// mode, and one defining `fn transform(text)` can be used as the output filter
+/// `{ script = "NAME" }`. An optional `fn description()` returns the text shown by `\?`.
+///
+/// Scripts reach the session through functions: `messages()` returns the conversation as maps
+/// of `role` and `content`, `model()` and `set_model(name)` get and change the model, and
+/// `send(prompt)` sends a prompt once the command returns.
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<Script>,
+    api: Arc<Mutex<Api>>,
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+}
+
+/// The session as seen by scripts, and the changes they request
+#[derive(Default)]
+pub struct Api {
+    pub messages: Vec<Message>,
+    pub model: String,
+    /// Model set with `set_model`
+    pub new_model: Option<String>,
+    /// Prompts queued with `send`
+    pub prompts: Vec<String>,
+}
+
+/// Directory of the scripts
+pub fn scripts_dir() -> PathBuf {
+    dirs::config_dir()
+        .or_else(dirs::home_dir)
+        .unwrap()
+        .join("heygpt")
+        .join("scripts")
+}
+
+impl Scripts {
+    /// Compile the scripts in the scripts directory, if any
+    pub fn load() -> Result<Self> {
+        let api = Arc::new(Mutex::new(Api::default()));
+        let engine = engine(&api);
+        let mut scripts = Vec::new();
+        let dir = scripts_dir();
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    engine,
+                    scripts,
+                    api,
+                })
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", dir.display()))
+            }
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            scripts.push(compile(&engine, &path)?);
+        }
+        Ok(Self {
+            engine,
+            scripts,
+            api,
+        })
+    }
+
+    /// Names and descriptions of the commands defined by scripts
+    pub fn commands(&self) -> Vec<(String, String)> {
+        self.scripts
+            .iter()
+            .filter(|script| defines(&script.ast, "command", 1))
+            .map(|script| {
+                let description = if defines(&script.ast, "description", 0) {
+                    self.call(script, "description", ()).unwrap_or_default()
+                } else {
+                    String::new()
+                };
+                (script.name.clone(), description)
+            })
+            .collect()
+    }
+
+    /// Run the command `name` with the given arguments, if a script defines it, returning the
+    /// text to print and the changes requested to the session
+    pub fn run_command(
+        &self,
+        name: &str,
+        args: &str,
+        messages: &[Message],
+        model: &str,
+    ) -> Option<Result<(String, Api)>> {
+        let script = self
+            .find(name)
+            .filter(|script| defines(&script.ast, "command", 1))?;
+        *self.api.lock().unwrap() = Api {
+            messages: messages.to_vec(),
+            model: model.to_owned(),
+            ..Api::default()
+        };
+        let output = self.call(script, "command", (args.to_owned(),));
+        let api = std::mem::take(&mut *self.api.lock().unwrap());
+        Some(output.map(|output| (output, api)))
+    }
+
+    /// Transform a response with the script `name`
+    pub fn transform(&self, name: &str, text: String) -> Result<String> {
+        let script = self
+            .find(name)
+            .filter(|script| defines(&script.ast, "transform", 1))
+            .ok_or_else(|| {
+                anyhow!(
+                    "No script {name}.rhai defining `fn transform(text)` in {}",
+                    scripts_dir().display()
+                )
+            })?;
+        self.call(script, "transform", (text,))
+    }
+
+    fn find(&self, name: &str) -> Option<&Script> {
+        self.scripts.iter().find(|script| script.name == name)
+    }
+
+    /// Call a function of a script, converting its result to text
+    fn call(&self, script: &Script, function: &str, args: impl rhai::FuncArgs) -> Result<String> {
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &script.ast, function, args)
+            .map_err(|err| anyhow!("{err}"))
+            .with_context(|| format!("Script {} failed", script.name))?;
+        Ok(if result.is_unit() {
+            String::new()
+        } else {
+            result.to_string()
+        })
+    }
+}
+
+fn compile(engine: &Engine, path: &Path) -> Result<Script> {
+    let name = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let ast = engine
+        .compile_file(path.to_owned())
+        .map_err(|err| anyhow!("{err}"))
+        .with_context(|| format!("Failed to compile the script {}", path.display()))?;
+    Ok(Script { name, ast })
+}
+
+fn defines(ast: &AST, function: &str, params: usize) -> bool {
+    ast.iter_functions()
+        .any(|f| f.name == function && f.params.len() == params)
+}
+
+/// The engine, with the session API bound to `api`
+fn engine(api: &Arc<Mutex<Api>>) -> Engine {
+    let mut engine = Engine::new();
+    let state = api.clone();
+    engine.register_fn("messages", move || -> Array {
+        let api = state.lock().unwrap();
+        api.messages
+            .iter()
+            .map(|message| {
+                let mut map = Map::new();
+                map.insert("role".into(), message.role.clone().into());
+                map.insert("content".into(), message.content.clone().into());
+                Dynamic::from_map(map)
+            })
+            .collect()
+    });
+    let state = api.clone();
+    engine.register_fn("model", move || -> String {
+        let api = state.lock().unwrap();
+        api.new_model.clone().unwrap_or_else(|| api.model.clone())
+    });
+    let state = api.clone();
+    engine.register_fn("set_model", move |model: &str| {
+        state.lock().unwrap().new_model = Some(model.to_owned());
+    });
+    let state = api.clone();
+    engine.register_fn("send", move |prompt: &str| {
+        state.lock().unwrap().prompts.push(prompt.to_owned());
+    });
+    engine
+}
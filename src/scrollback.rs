@@ -0,0 +1,85 @@
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+/// Environment variable pointing to a terminal log written by a shell integration,
+/// e.g. `script -f ~/.terminal.log`
+const SCROLLBACK_FILE_ENV: &str = "HEYGPT_SCROLLBACK_FILE";
+
+/// Capture the last `lines` lines of terminal output preceding the heygpt command itself.
+///
+/// Inside tmux the current pane is captured; otherwise the log file named by
+/// `HEYGPT_SCROLLBACK_FILE` is read.
+pub fn capture(lines: usize) -> Result<String> {
+    let output = if std::env::var_os("TMUX").is_some() {
+        capture_tmux(lines)?
+    } else if let Some(path) = std::env::var_os(SCROLLBACK_FILE_ENV) {
+        let content = std::fs::read(&path)
+            .with_context(|| format!("Failed to read {}", path.to_string_lossy()))?;
+        let content = String::from_utf8_lossy(&content);
+        let content = content.trim_end();
+        // The last line is the command line of heygpt itself
+        let mut all: Vec<&str> = content.lines().collect();
+        all.pop();
+        all[all.len().saturating_sub(lines)..].join("\n")
+    } else {
+        bail!("--scrollback requires running inside tmux or setting {SCROLLBACK_FILE_ENV}");
+    };
+    Ok(strip_ansi(output.trim_matches('\n')))
+}
+
+fn capture_tmux(lines: usize) -> Result<String> {
+    // Stop right above the cursor line, which holds the heygpt command line
+    let cursor_y = tmux(&["display-message", "-p", "#{cursor_y}"])?;
+    let end = cursor_y.trim().parse::<i64>()? - 1;
+    let start = end - lines as i64 + 1;
+    tmux(&[
+        "capture-pane",
+        "-p",
+        "-J",
+        "-S",
+        &start.to_string(),
+        "-E",
+        &end.to_string(),
+    ])
+}
+
+fn tmux(args: &[&str]) -> Result<String> {
+    let output = Command::new("tmux")
+        .args(args)
+        .output()
+        .context("Failed to run tmux")?;
+    if !output.status.success() {
+        bail!(
+            "tmux {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Remove ANSI escape sequences which terminal logs are usually full of
+fn strip_ansi(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                if chars.next_if_eq(&'[').is_some() {
+                    // CSI sequence ends with a byte in range 0x40..=0x7e
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                } else {
+                    chars.next();
+                }
+            }
+            '\r' => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
@@ -0,0 +1,535 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use log::debug;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::attachment::Attachment;
+use crate::blobs;
+use crate::model::{Message, MessageMeta};
+use crate::storage::{atomic_write, atomic_write_private, cache_dir, data_dir};
+
+/// An interactive session saved in `~/.local/share/heygpt/sessions`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SavedSession {
+    pub id: String,
+    /// Seconds since UNIX epoch
+    pub created: u64,
+    /// Seconds since UNIX epoch
+    pub updated: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Attached files, stored in the blob store and referenced from the messages
+    #[serde(default)]
+    pub attachments: Vec<AttachmentRef>,
+    pub messages: Vec<SavedMessage>,
+    /// Whether the session has never been saved, so its ID is not claimed yet
+    #[serde(skip)]
+    unsaved: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AttachmentRef {
+    pub name: String,
+    pub lang: String,
+    /// Hash of the content in the blob store
+    pub blob: String,
+    /// Index of the message the attachment was sent with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<usize>,
+}
+
+impl AttachmentRef {
+    /// Placeholder replacing the content of the attachment in saved messages
+    pub fn marker(&self) -> String {
+        format!("[[blob:{}]]", self.blob)
+    }
+}
+
+/// Idle time after which the conversation of the last one-shot invocation isn't continued
+const LAST_CONVERSATION_TTL: u64 = 60 * 60;
+
+/// The conversation of the last one-shot invocation in a terminal, for `--continue-last`
+#[derive(Serialize, Deserialize, Debug)]
+struct LastConversation {
+    /// Seconds since UNIX epoch
+    updated: u64,
+    messages: Vec<Message>,
+}
+
+/// The messages of the last one-shot conversation in this terminal, unless it expired
+pub fn load_last_conversation() -> Vec<Message> {
+    let path = last_conversation_path();
+    let last = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<LastConversation>(&content).ok());
+    match last {
+        Some(last) if now().saturating_sub(last.updated) < LAST_CONVERSATION_TTL => last.messages,
+        _ => {
+            debug!("No recent conversation in {}", path.display());
+            Vec::new()
+        }
+    }
+}
+
+pub fn save_last_conversation(messages: &[Message]) -> Result<()> {
+    let last = LastConversation {
+        updated: now(),
+        messages: messages.to_vec(),
+    };
+    atomic_write_private(&last_conversation_path(), serde_json::to_string(&last)?)
+}
+
+/// Kept per terminal, so that conversations in different terminals don't mix
+fn last_conversation_path() -> PathBuf {
+    let terminal = terminal_name().unwrap_or_else(|| "default".to_owned());
+    cache_dir()
+        .join("last")
+        .join(terminal.trim_start_matches('/').replace('/', "-") + ".json")
+}
+
+/// Name of the terminal, e.g. `/dev/pts/3`, also when stdin and stdout are redirected
+#[cfg(unix)]
+fn terminal_name() -> Option<String> {
+    [libc::STDERR_FILENO, libc::STDOUT_FILENO, libc::STDIN_FILENO]
+        .into_iter()
+        .find_map(|fd| {
+            // SAFETY: `ttyname` returns null or a string valid until the next call
+            unsafe {
+                let name = libc::ttyname(fd);
+                (!name.is_null()).then(|| {
+                    std::ffi::CStr::from_ptr(name)
+                        .to_string_lossy()
+                        .into_owned()
+                })
+            }
+        })
+}
+
+#[cfg(not(unix))]
+fn terminal_name() -> Option<String> {
+    None
+}
+
+/// Limits of the saved sessions, from `[retention]` in the config file. The oldest sessions
+/// are removed first.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct Retention {
+    pub max_sessions: Option<usize>,
+    pub max_age_days: Option<u64>,
+    /// Total size of the sessions and their attachments
+    pub max_size_mb: Option<u64>,
+}
+
+impl Retention {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_sessions.is_none() && self.max_age_days.is_none() && self.max_size_mb.is_none()
+    }
+}
+
+/// Storage format of saved sessions, from `session_format` in the config file
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionFormat {
+    /// Pretty-printed JSON, in `ID.json`
+    #[default]
+    Json,
+    /// zstd-compressed MessagePack, in `ID.msgpack.zst`, several times smaller
+    Compact,
+}
+
+impl SessionFormat {
+    const ALL: [Self; 2] = [Self::Json, Self::Compact];
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Json => ".json",
+            Self::Compact => ".msgpack.zst",
+        }
+    }
+
+    fn path(self, id: &str) -> PathBuf {
+        sessions_dir().join(format!("{id}{}", self.extension()))
+    }
+
+    /// The format and session ID of a file in the sessions directory
+    fn of_file(name: &str) -> Option<(Self, &str)> {
+        Self::ALL
+            .into_iter()
+            .find_map(|format| Some((format, name.strip_suffix(format.extension())?)))
+            .filter(|(_, id)| !id.is_empty() && !id.starts_with('.'))
+    }
+
+    fn encode(self, session: &SavedSession) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Json => serde_json::to_vec_pretty(session)?,
+            // With field names, so that optional and defaulted fields keep working
+            #[cfg(feature = "compact-sessions")]
+            Self::Compact => zstd::encode_all(&rmp_serde::to_vec_named(session)?[..], 0)?,
+            #[cfg(not(feature = "compact-sessions"))]
+            Self::Compact => anyhow::bail!(NO_COMPACT_SESSIONS),
+        })
+    }
+
+    fn decode(self, content: &[u8]) -> Result<SavedSession> {
+        Ok(match self {
+            Self::Json => serde_json::from_slice(content)?,
+            #[cfg(feature = "compact-sessions")]
+            Self::Compact => rmp_serde::from_slice(&zstd::decode_all(content)?)?,
+            #[cfg(not(feature = "compact-sessions"))]
+            Self::Compact => anyhow::bail!(NO_COMPACT_SESSIONS),
+        })
+    }
+}
+
+#[cfg(not(feature = "compact-sessions"))]
+const NO_COMPACT_SESSIONS: &str =
+    "heygpt was built without the `compact-sessions` feature, needed for compact sessions";
+
+/// What `heygpt history` needs to know about a saved session, kept in an index so that
+/// listing thousands of sessions doesn't load them all
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionSummary {
+    pub id: String,
+    /// Seconds since UNIX epoch
+    pub updated: u64,
+    pub tags: Vec<String>,
+    pub title: String,
+    /// Hashes of the attached files in the blob store
+    pub blobs: Vec<String>,
+    /// Name of the session file in the sessions directory
+    file: String,
+    /// Size of the session file
+    pub size: u64,
+    /// Modification time of the session file in nanoseconds since UNIX epoch, to detect
+    /// changes along with the size
+    mtime: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SavedMessage {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<MessageMeta>,
+}
+
+impl SavedSession {
+    /// A new session, identified by its creation time and the process that created it
+    pub fn new() -> Self {
+        let now = now();
+        Self {
+            id: format!(
+                "{}-{}",
+                format_time(now).replace([' ', ':'], "-"),
+                std::process::id()
+            ),
+            created: now,
+            updated: now,
+            tags: Vec::new(),
+            attachments: Vec::new(),
+            messages: Vec::new(),
+            unsaved: true,
+        }
+    }
+
+    /// Load a session saved in any format
+    pub fn load(id: &str) -> Result<Self> {
+        let (format, path) = SessionFormat::ALL
+            .into_iter()
+            .map(|format| (format, format.path(id)))
+            .find(|(_, path)| path.exists())
+            .with_context(|| format!("No session {id}"))?;
+        let content = std::fs::read(&path).with_context(|| format!("No session {id}"))?;
+        format
+            .decode(&content)
+            .with_context(|| format!("Invalid session {id}"))
+    }
+
+    /// Save the session in the given format, removing it in the other formats
+    pub fn save(&mut self, format: SessionFormat) -> Result<()> {
+        self.updated = now();
+        if self.unsaved {
+            self.claim_id(format)?;
+        }
+        self.write(format)
+    }
+
+    /// Create the file of a new session exclusively, so that another instance saving a
+    /// session with the same ID doesn't overwrite it. On a collision, a counter is appended.
+    fn claim_id(&mut self, format: SessionFormat) -> Result<()> {
+        std::fs::create_dir_all(sessions_dir())?;
+        let base = self.id.clone();
+        for n in 2.. {
+            let taken = SessionFormat::ALL
+                .into_iter()
+                .filter(|&f| f != format)
+                .any(|f| f.path(&self.id).exists());
+            if !taken {
+                match std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(format.path(&self.id))
+                {
+                    Ok(_) => break,
+                    Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+                    Err(err) => return Err(err).context("Failed to create the session file"),
+                }
+            }
+            self.id = format!("{base}-{n}");
+        }
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Save the session without changing the time of its last update
+    pub fn write(&self, format: SessionFormat) -> Result<()> {
+        atomic_write(&format.path(&self.id), format.encode(self)?)?;
+        for other in SessionFormat::ALL.into_iter().filter(|&f| f != format) {
+            match std::fs::remove_file(other.path(&self.id)) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the messages. The content of attachments is moved to the blob store, so that
+    /// files attached repeatedly or to several sessions are stored once.
+    pub fn set_messages(&mut self, messages: &[Message], attached: &[Attachment]) -> Result<()> {
+        self.attachments = attached
+            .iter()
+            .map(|a| {
+                Ok(AttachmentRef {
+                    name: a.name.clone(),
+                    lang: a.lang.clone(),
+                    blob: blobs::store(a.content.trim_end())?,
+                    message: a.message,
+                })
+            })
+            .collect::<Result<_>>()?;
+        self.messages = messages
+            .iter()
+            .map(|m| {
+                let content = attached
+                    .iter()
+                    .zip(&self.attachments)
+                    .filter(|(a, _)| !a.content.trim_end().is_empty())
+                    .fold(m.content.clone(), |content, (a, r)| {
+                        content.replace(a.content.trim_end(), &r.marker())
+                    });
+                SavedMessage {
+                    role: m.role.clone(),
+                    content,
+                    meta: m.meta.clone(),
+                }
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Content of a message, with the attachments restored from the blob store
+    pub fn content(&self, message: &SavedMessage) -> Result<String> {
+        let mut content = message.content.clone();
+        for r in &self.attachments {
+            if content.contains(&r.marker()) {
+                content = content.replace(&r.marker(), &blobs::load(&r.blob)?);
+            }
+        }
+        Ok(content)
+    }
+
+    /// Add tags, given as a comma separated list
+    pub fn add_tags(&mut self, tags: &str) {
+        for tag in tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if !self.tags.iter().any(|t| t == tag) {
+                self.tags.push(tag.to_owned());
+            }
+        }
+    }
+
+    /// The first user message, shortened to a single line
+    pub fn title(&self) -> String {
+        let first = self
+            .messages
+            .iter()
+            .find(|m| m.role == "user")
+            .map_or("", |m| m.content.as_str());
+        let line = first.lines().next().unwrap_or_default();
+        match line.char_indices().nth(60) {
+            Some((i, _)) => format!("{}…", &line[..i]),
+            None => line.to_owned(),
+        }
+    }
+}
+
+pub fn sessions_dir() -> PathBuf {
+    data_dir().join("sessions")
+}
+
+impl SessionSummary {
+    /// Whether the session is saved in the given format
+    pub fn is_in(&self, format: SessionFormat) -> bool {
+        SessionFormat::of_file(&self.file).is_some_and(|(f, _)| f == format)
+    }
+}
+
+/// Summaries of all saved sessions, most recently updated first.
+///
+/// Summaries come from an index in the cache directory. Only sessions added or changed since
+/// the index was written are loaded, e.g. by other instances or `heygpt sync`, and the index
+/// is updated then.
+pub fn list() -> Result<Vec<SessionSummary>> {
+    let entries = match std::fs::read_dir(sessions_dir()) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut index = load_index();
+    let indexed = index.len();
+    let mut changed = false;
+    let mut sessions = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some((format, id)) = SessionFormat::of_file(&name) else {
+            continue;
+        };
+        let metadata = entry.metadata()?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        match index.remove(&name) {
+            Some(summary) if summary.size == metadata.len() && summary.mtime == mtime => {
+                sessions.push(summary);
+            }
+            _ => {
+                changed = true;
+                let content = std::fs::read(entry.path())?;
+                match format.decode(&content) {
+                    Ok(session) => sessions.push(SessionSummary {
+                        id: id.to_owned(),
+                        updated: session.updated,
+                        title: session.title(),
+                        tags: session.tags,
+                        blobs: session.attachments.into_iter().map(|a| a.blob).collect(),
+                        file: name,
+                        size: metadata.len(),
+                        mtime,
+                    }),
+                    Err(err) => log::warn!("Skipping invalid session {name}: {err}"),
+                }
+            }
+        }
+    }
+    // Entries left in the index are for removed sessions
+    if changed || sessions.len() != indexed {
+        if let Err(err) = save_index(&sessions) {
+            log::warn!("Failed to save the session index: {err}");
+        }
+    }
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.updated));
+    // A session in two formats, if converting it was interrupted
+    let mut seen = HashSet::new();
+    sessions.retain(|s| seen.insert(s.id.clone()));
+    Ok(sessions)
+}
+
+/// Remove a saved session in any format
+pub fn remove(id: &str) -> Result<()> {
+    for format in SessionFormat::ALL {
+        match std::fs::remove_file(format.path(id)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn index_path() -> PathBuf {
+    cache_dir().join("sessions-index.json")
+}
+
+/// The index of session summaries by file name, empty if missing or invalid
+fn load_index() -> HashMap<String, SessionSummary> {
+    let summaries: Vec<SessionSummary> = std::fs::read(index_path())
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default();
+    summaries.into_iter().map(|s| (s.file.clone(), s)).collect()
+}
+
+fn save_index(sessions: &[SessionSummary]) -> Result<()> {
+    atomic_write(&index_path(), serde_json::to_vec(sessions)?)
+}
+
+/// Remove the sessions exceeding the retention limits, and the attachments only they
+/// referenced. Returns the number of removed sessions.
+pub fn prune(retention: &Retention) -> Result<usize> {
+    let now = now();
+    let mut removed = 0;
+    let mut size = 0;
+    let mut referenced = HashSet::new();
+    let sessions = list()?;
+    for (i, session) in sessions.iter().enumerate() {
+        // The size of an attachment counts for the newest session referencing it
+        let session_size = session.size
+            + session
+                .blobs
+                .iter()
+                .filter(|blob| !referenced.contains(blob.as_str()))
+                .map(|blob| blobs::size(blob))
+                .sum::<u64>();
+        let exceeded = retention.max_sessions.is_some_and(|max| i >= max)
+            || retention
+                .max_age_days
+                .is_some_and(|days| now.saturating_sub(session.updated) > days * 86400)
+            || retention
+                .max_size_mb
+                .is_some_and(|mb| size + session_size > mb * 1024 * 1024);
+        if exceeded {
+            remove(&session.id)?;
+            removed += 1;
+        } else {
+            size += session_size;
+            referenced.extend(session.blobs.iter().map(String::as_str));
+        }
+    }
+    if removed > 0 {
+        blobs::remove_unreferenced(&referenced)?;
+    }
+    Ok(removed)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Format seconds since UNIX epoch as `YYYY-MM-DD HH:MM:SS` in UTC
+pub fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
@@ -0,0 +1,134 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Directory for cached data, e.g. `~/.cache/heygpt` on Linux
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("heygpt")
+}
+
+/// Directory for persistent data, e.g. `~/.local/share/heygpt` on Linux
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .or_else(dirs::home_dir)
+        .unwrap()
+        .join("heygpt")
+}
+
+/// Directory for files of running instances, e.g. sockets: `$XDG_RUNTIME_DIR/heygpt` on
+/// Linux, or the cache directory
+pub fn runtime_dir() -> PathBuf {
+    dirs::runtime_dir()
+        .map(|dir| dir.join("heygpt"))
+        .unwrap_or_else(cache_dir)
+}
+
+/// Write a file atomically, so that concurrent readers and writers, e.g. other heygpt
+/// instances, never see it partially written. An existing file keeps its permissions.
+pub fn atomic_write(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    write_replacing(path, contents.as_ref(), false)
+}
+
+/// Write a file holding secrets atomically, such as the config with the API key. A new file is
+/// only readable by the user, and an existing file keeps its permissions.
+pub fn atomic_write_private(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    write_replacing(path, contents.as_ref(), true)
+}
+
+fn write_replacing(path: &Path, contents: &[u8], private: bool) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    // The temporary file must be on the same file system for the rename to be atomic
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    let permissions = std::fs::metadata(path).ok().map(|m| m.permissions());
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Created with the final mode, so that the contents are never readable by others
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        match &permissions {
+            Some(permissions) => options.mode(permissions.mode() & 0o777),
+            None if private => options.mode(0o600),
+            None => &mut options,
+        };
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(contents))
+        .and_then(|_| match permissions {
+            // Also when the temporary file existed, or the umask removed some bits
+            Some(permissions) => std::fs::set_permissions(&tmp, permissions),
+            None => Ok(()),
+        })
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&tmp);
+        })
+}
+
+/// An exclusive lock on a file, released when dropped
+#[cfg(unix)]
+pub struct Lock(std::os::unix::io::RawFd);
+
+#[cfg(unix)]
+impl Lock {
+    /// Wait for the lock
+    pub fn new(file: &File) -> Result<Self> {
+        Self::flock(file, libc::LOCK_EX)?.context("The file is locked")
+    }
+
+    /// Take the lock unless it's held, e.g. by another process
+    pub fn try_new(file: &File) -> Result<Option<Self>> {
+        Self::flock(file, libc::LOCK_EX | libc::LOCK_NB)
+    }
+
+    fn flock(file: &File, operation: libc::c_int) -> Result<Option<Self>> {
+        use std::os::unix::io::AsRawFd;
+        let fd = file.as_raw_fd();
+        // SAFETY: `fd` is an open file descriptor owned by `file`, which outlives the lock
+        if unsafe { libc::flock(fd, operation) } == 0 {
+            return Ok(Some(Self(fd)));
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() == std::io::ErrorKind::WouldBlock {
+            return Ok(None);
+        }
+        Err(err.into())
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // SAFETY: see `Lock::flock`
+        unsafe { libc::flock(self.0, libc::LOCK_UN) };
+    }
+}
+
+/// Files aren't locked on this platform
+#[cfg(not(unix))]
+pub struct Lock;
+
+#[cfg(not(unix))]
+impl Lock {
+    pub fn new(_file: &File) -> Result<Self> {
+        Ok(Self)
+    }
+
+    /// Without locks, a lock always seems held by another process
+    pub fn try_new(_file: &File) -> Result<Option<Self>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,303 @@
+use std::iter::once;
+use std::path::Path;
+
+use anyhow::Result;
+use clap::Parser;
+use console::style;
+
+use crate::Session;
+
+mod audit;
+mod config;
+mod crate_docs;
+mod deps;
+mod docker;
+mod doctor;
+#[cfg(feature = "eval")]
+mod eval;
+mod history;
+mod ops;
+mod pbpatch;
+mod pricing;
+mod prompt_lint;
+mod quiz;
+mod send;
+mod sweep;
+mod sync;
+mod tour;
+mod write;
+
+/// Subcommands listed in `--help`
+pub const HELP: &str = "\
+Subcommands (given as the first word of the prompt, see `heygpt <subcommand> --help`;
+quote the prompt to send it to the model instead):
+  audit        Verify the audit log written with --audit-log
+  config       Change, export and import the configuration
+  crate        Explain the API of a crate with docs from docs.rs
+  deps         Summarize dependencies and licenses of a Cargo project
+  docker       Generate or critique a Dockerfile for the current project
+  doctor       Check the API key, endpoint and model configuration
+  eval         Run an evaluation suite of prompts and report failures
+  history      List, export, migrate and prune saved interactive sessions
+  ops          Troubleshoot Kubernetes and other ops issues
+  pbpatch      Apply a code block from the clipboard to the working tree
+  pricing      Update and show the prices used to estimate costs
+  prompt-lint  Analyze a prompt template and propose an improved version
+  quiz         Run a quiz on a topic and keep score
+  send         Send a message to an interactive session started with --listen
+  sweep        Compare the outputs of a prompt across parameter values
+  sync         Synchronize saved sessions with a git remote
+  tour         Walk through the features of heygpt without using the API
+  write        Write a long document section by section from an outline";
+
+/// A subcommand with its arguments
+#[derive(Debug)]
+pub enum Command {
+    Audit(audit::Args),
+    Config(config::Args),
+    Crate(crate_docs::Args),
+    Deps(deps::Args),
+    Docker(docker::Args),
+    Doctor(doctor::Args),
+    #[cfg(feature = "eval")]
+    Eval(eval::Args),
+    History(history::Args),
+    Ops(ops::Args),
+    Pbpatch(pbpatch::Args),
+    Pricing(pricing::Args),
+    PromptLint(prompt_lint::Args),
+    Quiz(quiz::Args),
+    Send(send::Args),
+    Sweep(sweep::Args),
+    Sync(sync::Args),
+    Tour(tour::Args),
+    Write(write::Args),
+}
+
+/// The subcommand named by the first word of the prompt, e.g. `heygpt ops ...`, with the
+/// remaining words parsed by the subcommand's own argument parser.
+///
+/// A prompt which merely starts with the name of a subcommand, e.g. `heygpt write a poem`, isn't
+/// valid arguments for it and is sent to the model, as is a quoted prompt such as
+/// `heygpt "history list"`, even with dashes further on, as in `heygpt write a haiku about -5
+/// degrees`. Fails with the message of the parser for a subcommand given alone or followed by
+/// an option, or for `--help`.
+pub fn parse(prompt: &[String]) -> Result<Option<Command>, clap::Error> {
+    let Some((name, args)) = prompt.split_first() else {
+        return Ok(None);
+    };
+    let argv: Vec<String> = once(format!("heygpt {name}"))
+        .chain(args.iter().cloned())
+        .collect();
+    let command = match name.as_str() {
+        "audit" => audit::Args::try_parse_from(argv).map(Command::Audit),
+        "config" => config::Args::try_parse_from(argv).map(Command::Config),
+        "crate" => crate_docs::Args::try_parse_from(argv).map(Command::Crate),
+        "deps" => deps::Args::try_parse_from(argv).map(Command::Deps),
+        "docker" => docker::Args::try_parse_from(argv).map(Command::Docker),
+        "doctor" => doctor::Args::try_parse_from(argv).map(Command::Doctor),
+        #[cfg(feature = "eval")]
+        "eval" => eval::Args::try_parse_from(argv).map(Command::Eval),
+        #[cfg(not(feature = "eval"))]
+        "eval" if args.first().is_some_and(|arg| Path::new(arg).is_file()) => {
+            return Err(clap::Error::raw(
+                clap::error::ErrorKind::InvalidSubcommand,
+                "heygpt was built without the `eval` feature\n",
+            ))
+        }
+        "history" => history::Args::try_parse_from(argv).map(Command::History),
+        "ops" => ops::Args::try_parse_from(argv).map(Command::Ops),
+        "pbpatch" => pbpatch::Args::try_parse_from(argv).map(Command::Pbpatch),
+        "pricing" => pricing::Args::try_parse_from(argv).map(Command::Pricing),
+        "prompt-lint" => prompt_lint::Args::try_parse_from(argv).map(Command::PromptLint),
+        "quiz" => quiz::Args::try_parse_from(argv).map(Command::Quiz),
+        "send" => send::Args::try_parse_from(argv).map(Command::Send),
+        "sweep" => sweep::Args::try_parse_from(argv).map(Command::Sweep),
+        "sync" => sync::Args::try_parse_from(argv).map(Command::Sync),
+        "tour" => tour::Args::try_parse_from(argv).map(Command::Tour),
+        "write" => write::Args::try_parse_from(argv).map(Command::Write),
+        _ => return Ok(None),
+    };
+    match command {
+        Ok(command) => Ok(Some(command)),
+        Err(err) if args.is_empty() => Err(err),
+        // An option right after the name is only meant for the subcommand
+        Err(err) if args[0].starts_with('-') && args[0].parse::<f64>().is_err() => Err(err),
+        Err(err) if err.kind() == clap::error::ErrorKind::DisplayHelp => Err(err),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Run a subcommand parsed by `parse`
+pub async fn run(session: &mut Session, command: Command) -> Result<()> {
+    match command {
+        Command::Audit(args) => audit::run(session, args).await,
+        Command::Config(args) => config::run(session, args).await,
+        Command::Crate(args) => crate_docs::run(session, args).await,
+        Command::Deps(args) => deps::run(session, args).await,
+        Command::Docker(args) => docker::run(session, args).await,
+        Command::Doctor(args) => doctor::run(session, args).await,
+        #[cfg(feature = "eval")]
+        Command::Eval(args) => eval::run(session, args).await,
+        Command::History(args) => history::run(session, args).await,
+        Command::Ops(args) => ops::run(session, args).await,
+        Command::Pbpatch(args) => pbpatch::run(session, args).await,
+        Command::Pricing(args) => pricing::run(session, args).await,
+        Command::PromptLint(args) => prompt_lint::run(session, args).await,
+        Command::Quiz(args) => quiz::run(session, args).await,
+        Command::Send(args) => send::run(session, args).await,
+        Command::Sweep(args) => sweep::run(session, args).await,
+        Command::Sync(args) => sync::run(session, args).await,
+        Command::Tour(args) => tour::run(session, args).await,
+        Command::Write(args) => write::run(session, args).await,
+    }
+}
+
+/// Whether the command needs an API key, i.e. is a prompt or a subcommand using the model
+pub fn needs_api_key(command: Option<&Command>) -> bool {
+    !matches!(
+        command,
+        Some(
+            Command::Audit(_)
+                | Command::Config(_)
+                | Command::History(_)
+                | Command::Pbpatch(_)
+                | Command::Pricing(_)
+                | Command::Send(_)
+                | Command::Sync(_)
+                | Command::Tour(_)
+        )
+    )
+}
+
+/// The built-in system prompt of a subcommand, unless overridden in the `[prompts]` table of
+/// the config file
+fn builtin_prompt(session: &Session, name: &str, builtin: &str) -> String {
+    session
+        .options
+        .prompts
+        .get(name)
+        .map_or(builtin, String::as_str)
+        .to_owned()
+}
+
+/// Combine the built-in system prompt of a subcommand with the one given by `--system`
+fn system_prompt(session: &Session, builtin: &str) -> String {
+    match &session.options.system {
+        Some(s) if !s.is_empty() => format!("{builtin}\n\n{s}"),
+        _ => builtin.to_owned(),
+    }
+}
+
+/// Print the changes to a file as a coloured unified diff
+fn print_diff(path: &Path, old: &str, new: &str) {
+    let name = path.display().to_string();
+    let (old_name, new_name) = if path.is_absolute() {
+        (name.clone(), name)
+    } else {
+        (format!("a/{name}"), format!("b/{name}"))
+    };
+    #[cfg(feature = "diff")]
+    let diff = similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&old_name, &new_name)
+        .to_string();
+    // The whole file is replaced
+    #[cfg(not(feature = "diff"))]
+    let diff = {
+        let mut diff = format!("--- {old_name}\n+++ {new_name}\n");
+        for line in old.lines() {
+            diff.push_str(&format!("-{line}\n"));
+        }
+        for line in new.lines() {
+            diff.push_str(&format!("+{line}\n"));
+        }
+        diff
+    };
+    for line in diff.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            println!("{}", style(line).bold());
+        } else if line.starts_with('+') {
+            println!("{}", style(line).green());
+        } else if line.starts_with('-') {
+            println!("{}", style(line).red());
+        } else if line.starts_with("@@") {
+            println!("{}", style(line).cyan());
+        } else {
+            println!("{line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(prompt: &str) -> Vec<String> {
+        prompt.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn prompts_starting_with_subcommand_names_go_to_the_model() {
+        for prompt in [
+            "write a poem about cats",
+            "history of rome",
+            "quiz me on rust",
+            "sync two folders with rsync",
+            "tour of paris in two days",
+            "doctor who episodes",
+            "config nginx as a reverse proxy",
+            "pricing of the cloud providers",
+        ] {
+            assert!(
+                matches!(parse(&words(prompt)), Ok(None)),
+                "{prompt} was taken as a subcommand"
+            );
+        }
+        // Quoted prompts are a single word
+        assert!(matches!(parse(&["history list".to_owned()]), Ok(None)));
+    }
+
+    #[test]
+    fn prompts_with_dashes_go_to_the_model() {
+        for prompt in [
+            "write a haiku about -5 degrees",
+            "history of the -ism suffix",
+            "quiz me on x-ray and -O3 flags",
+            "write -5 reasons to learn rust",
+        ] {
+            assert!(
+                matches!(parse(&words(prompt)), Ok(None)),
+                "{prompt} was taken as a subcommand"
+            );
+        }
+    }
+
+    #[test]
+    fn subcommands_with_valid_arguments_are_run() {
+        assert!(matches!(
+            parse(&words("history list --tag work")),
+            Ok(Some(Command::History(_)))
+        ));
+        assert!(matches!(
+            parse(&words("quiz --topic rust --n 3")),
+            Ok(Some(Command::Quiz(_)))
+        ));
+        assert!(matches!(
+            parse(&words("ops why is it crashlooping")),
+            Ok(Some(Command::Ops(_)))
+        ));
+        assert!(matches!(
+            parse(&words("doctor")),
+            Ok(Some(Command::Doctor(_)))
+        ));
+    }
+
+    #[test]
+    fn invalid_subcommand_options_are_errors() {
+        assert!(parse(&words("write --outline plan.md")).is_err());
+        assert!(parse(&words("history")).is_err());
+        assert!(parse(&words("quiz --help")).is_err());
+    }
+}
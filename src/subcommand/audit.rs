@@ -0,0 +1,46 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use console::style;
+
+use crate::audit;
+use crate::Session;
+
+/// Check the audit log written with `--audit-log`
+#[derive(Parser, Debug)]
+#[command(disable_help_subcommand = true)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Action {
+    /// Verify that no entry of the audit log was edited, inserted or removed
+    Verify {
+        /// The audit log (default: audit_log from the config file)
+        #[arg(long, value_name = "PATH")]
+        file: Option<String>,
+    },
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    match args.action {
+        Action::Verify { file } => {
+            let Some(file) = file.or_else(|| session.options.audit_log.clone()) else {
+                bail!("Specify the audit log with --file or audit_log in ~/.heygpt.toml");
+            };
+            let path = audit::log_path(&file);
+            let count = audit::verify(&path)?;
+            println!(
+                "{}",
+                style(format!(
+                    "The audit log {} is intact: {count} entries",
+                    path.display()
+                ))
+                .bold()
+                .green()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,407 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use console::style;
+use schemars::schema::{RootSchema, Schema};
+use schemars::schema_for;
+use toml_edit::{Document, Item, Key, Table, TableLike, Value};
+
+use crate::i18n::is_yes;
+use crate::storage::{atomic_write, atomic_write_private};
+use crate::{config_file_path, parse_config, Options, Session};
+
+/// Settings which are never exported or imported, at any depth, e.g. also `api_key` of a
+/// profile
+const SECRETS: &[&str] = &["api_key"];
+
+/// Change, export and import the configuration, or print its JSON schema, e.g. to set up heygpt
+/// on a new machine or share a setup with a teammate
+#[derive(Parser, Debug)]
+#[command(disable_help_subcommand = true)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Action {
+    /// Set a setting, e.g. `model gpt-4o` or `retention.max_sessions 1000`
+    Set {
+        /// Setting, with dots for nested tables, e.g. `profiles.work.model`
+        key: String,
+        /// Value in TOML syntax, e.g. `0.5`, `true` or `["a", "b"]`, or else a string
+        value: String,
+        /// Save without confirming the changes
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Remove a setting, going back to its default
+    Unset {
+        /// Setting, with dots for nested tables
+        key: String,
+        /// Save without confirming the changes
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Edit the config file with $VISUAL or $EDITOR, checking it before saving
+    Edit {
+        /// Save without confirming the changes
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Write the configuration without secrets such as the API key
+    Export {
+        /// File to write (default: stdout)
+        file: Option<PathBuf>,
+    },
+    /// Merge an exported configuration into the local one, keeping the local secrets
+    Import {
+        /// File written by `export`
+        file: PathBuf,
+    },
+    /// Print the JSON schema of the config file, for editors and other tools
+    Schema,
+}
+
+pub async fn run(_session: &mut Session, args: Args) -> Result<()> {
+    match args.action {
+        Action::Set { key, value, yes } => {
+            let path = parse_key(&key)?;
+            let old = read_config()?;
+            let new = set(&old, &path, &key, &value)?;
+            save(&old, &new, yes)?;
+        }
+        Action::Unset { key, yes } => {
+            let path = parse_key(&key)?;
+            let old = read_config()?;
+            let mut new = old.clone();
+            remove(&mut new, &path)
+                .ok_or_else(|| anyhow!("{key} isn't set in {}", config_file_path().display()))?;
+            parse_config(&new.to_string())?;
+            save(&old, &new, yes)?;
+        }
+        Action::Edit { yes } => {
+            let old = read_config()?;
+            // Next to the config, readable only by the user as it holds the API key, and
+            // removed when dropped, whichever way this returns
+            let path = config_file_path();
+            let mut file = tempfile::Builder::new()
+                .prefix(".heygpt-edit-")
+                .suffix(".toml")
+                .tempfile_in(path.parent().unwrap_or(Path::new(".")))
+                .context("Failed to create a temporary file to edit")?;
+            file.write_all(old.to_string().as_bytes())?;
+            file.flush()?;
+            let new = loop {
+                edit(file.path())?;
+                match validate(&std::fs::read_to_string(file.path())?) {
+                    Ok(new) => break new,
+                    Err(err) => {
+                        eprintln!("{}: {err:#}", style("Error").bold().red());
+                        if !confirm("Edit again?").unwrap_or(false) {
+                            bail!("Not saved");
+                        }
+                    }
+                }
+            };
+            drop(file);
+            save(&old, &new, yes)?;
+        }
+        Action::Export { file } => {
+            let mut config = read_config()?;
+            for path in secrets(&config) {
+                remove(&mut config, &path);
+            }
+            let bundle = format!("# heygpt configuration, exported without secrets\n{config}");
+            match file {
+                Some(file) => atomic_write(&file, bundle)?,
+                None => print!("{bundle}"),
+            }
+        }
+        Action::Import { file } => {
+            let content = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            parse_config(&content).with_context(|| format!("Invalid config {}", file.display()))?;
+            let mut imported: Document = content.parse()?;
+            for path in secrets(&imported) {
+                remove(&mut imported, &path);
+            }
+
+            let mut config = read_config()?;
+            // Put back after replacing the tables holding them, e.g. `[profiles]`
+            let local_secrets: Vec<(Vec<Key>, Item)> = secrets(&config)
+                .into_iter()
+                .filter_map(|path| get(&config, &path).cloned().map(|item| (path, item)))
+                .collect();
+            let mut count = 0;
+            for (key, item) in imported.iter() {
+                config[key] = item.clone();
+                count += 1;
+            }
+            for (path, secret) in local_secrets {
+                // Unless the import removed its table, e.g. a profile
+                let parent = &path[..path.len() - 1];
+                if parent.is_empty() || get(&config, parent).is_some_and(Item::is_table_like) {
+                    *item_mut(&mut config, &path, &dotted(&path))? = secret;
+                }
+            }
+            parse_config(&config.to_string())?;
+
+            let path = config_file_path();
+            backup(&path)?;
+            atomic_write_private(&path, config.to_string())?;
+            eprintln!(
+                "{}",
+                style(format!("Imported {count} settings into {}", path.display()))
+                    .bold()
+                    .green()
+            );
+        }
+        Action::Schema => println!("{}", serde_json::to_string_pretty(&schema())?),
+    }
+    Ok(())
+}
+
+/// JSON schema of the config file, with the descriptions and defaults of the options
+fn schema() -> RootSchema {
+    let mut schema = schema_for!(Options);
+    schema.schema.metadata().title = Some("heygpt configuration".to_owned());
+    schema.schema.metadata().description = Some("~/.heygpt.toml".to_owned());
+    // Options of a single invocation can't be set in the config file
+    if let Some(object) = &mut schema.schema.object {
+        object.properties.retain(|_, property| match property {
+            Schema::Object(o) => !o.metadata.as_ref().is_some_and(|m| m.read_only),
+            Schema::Bool(_) => true,
+        });
+    }
+    // Drop the definitions only used by those
+    let referenced = serde_json::to_string(&schema.schema).unwrap_or_default();
+    schema
+        .definitions
+        .retain(|name, _| referenced.contains(&format!("#/definitions/{name}\"")));
+    schema
+}
+
+/// The config file, keeping its comments and formatting. Empty if it doesn't exist.
+fn read_config() -> Result<Document> {
+    let path = config_file_path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    content
+        .parse()
+        .with_context(|| format!("Invalid config {}", path.display()))
+}
+
+/// Path of a setting in the config file, e.g. `profiles.work."gpt-4.1"`, checking that it
+/// starts with a known setting
+fn parse_key(key: &str) -> Result<Vec<Key>> {
+    let path = Key::parse(key).map_err(|err| anyhow!("Invalid key {key}: {err}"))?;
+    check_setting(path[0].get())?;
+    Ok(path)
+}
+
+fn check_setting(name: &str) -> Result<()> {
+    let schema = schema();
+    let known = schema
+        .schema
+        .object
+        .as_ref()
+        .is_some_and(|object| object.properties.contains_key(name));
+    if !known {
+        bail!("Unknown setting {name}, see `heygpt config schema` for the settings");
+    }
+    Ok(())
+}
+
+/// The config with the setting at `path` set to `value`, parsed as TOML or else taken as a
+/// string, e.g. for a model named `4` or `true`
+fn set(config: &Document, path: &[Key], key: &str, value: &str) -> Result<Document> {
+    let string = Value::from(value);
+    let parsed = value.parse::<Value>().unwrap_or_else(|_| string.clone());
+    let is_string = parsed.is_str();
+    let result = with_value(config, path, key, parsed);
+    match result {
+        Err(_) if !is_string => with_value(config, path, key, string).or(result),
+        _ => result,
+    }
+}
+
+/// The config with the setting at `path` set to `value`, if valid
+fn with_value(config: &Document, path: &[Key], key: &str, value: Value) -> Result<Document> {
+    let mut new = config.clone();
+    let item = item_mut(&mut new, path, key)?;
+    // Keep a comment after the previous value
+    let value = match item.as_value() {
+        Some(previous) => {
+            let mut value = value;
+            *value.decor_mut() = previous.decor().clone();
+            value
+        }
+        None => value.decorated(" ", ""),
+    };
+    *item = Item::Value(value);
+    parse_config(&new.to_string()).with_context(|| format!("Invalid value for {key}"))?;
+    Ok(new)
+}
+
+/// The item at `path`, creating the missing tables on the way
+fn item_mut<'a>(config: &'a mut Document, path: &[Key], key: &str) -> Result<&'a mut Item> {
+    let mut item = config.as_item_mut();
+    for (i, name) in path.iter().enumerate() {
+        if item.is_none() {
+            let mut table = Table::new();
+            // No empty `[profiles]` header above `[profiles.work]`
+            table.set_implicit(true);
+            *item = Item::Table(table);
+        }
+        let table = item.as_table_like_mut().ok_or_else(|| {
+            let parent: Vec<&str> = path[..i].iter().map(Key::get).collect();
+            anyhow!("Can't set {key}, {} isn't a table", parent.join("."))
+        })?;
+        item = table.entry(name.get()).or_insert(Item::None);
+    }
+    Ok(item)
+}
+
+/// Paths of the secrets in the config, at any depth
+fn secrets(config: &Document) -> Vec<Vec<Key>> {
+    fn walk(table: &dyn TableLike, prefix: &[Key], paths: &mut Vec<Vec<Key>>) {
+        for (name, item) in table.iter() {
+            let mut path = prefix.to_vec();
+            path.push(Key::new(name));
+            if SECRETS.contains(&name) {
+                paths.push(path);
+            } else if let Some(table) = item.as_table_like() {
+                walk(table, &path, paths);
+            }
+        }
+    }
+    let mut paths = Vec::new();
+    walk(config.as_table(), &[], &mut paths);
+    paths
+}
+
+fn get<'a>(config: &'a Document, path: &[Key]) -> Option<&'a Item> {
+    path.iter()
+        .try_fold(config.as_item(), |item, key| item.get(key.get()))
+}
+
+/// Remove the setting at `path`, returning it if it was set
+fn remove(config: &mut Document, path: &[Key]) -> Option<Item> {
+    let (last, parents) = path.split_last()?;
+    parents
+        .iter()
+        .try_fold(config.as_item_mut(), |item, key| item.get_mut(key.get()))
+        .and_then(Item::as_table_like_mut)
+        .and_then(|table| table.remove(last.get()))
+}
+
+fn dotted(path: &[Key]) -> String {
+    let names: Vec<String> = path
+        .iter()
+        .map(|key| key.display_repr().into_owned())
+        .collect();
+    names.join(".")
+}
+
+/// Parse an edited config, checking its settings
+fn validate(content: &str) -> Result<Document> {
+    let config: Document = content.parse()?;
+    for (name, _) in config.iter() {
+        check_setting(name)?;
+    }
+    parse_config(content)?;
+    Ok(config)
+}
+
+/// Open a file in the editor of the user, waiting for it to exit
+fn edit(file: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    // Editors are often given with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("$EDITOR is empty")?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(file)
+        .status()
+        .with_context(|| format!("Failed to run the editor {editor}"))?;
+    if !status.success() {
+        bail!("The editor {editor} failed, the config is unchanged");
+    }
+    Ok(())
+}
+
+/// Show the changes to the config file and write them once confirmed, keeping the previous
+/// version as a backup
+fn save(old: &Document, new: &Document, yes: bool) -> Result<()> {
+    let path = config_file_path();
+    if new.to_string() == old.to_string() {
+        eprintln!("{} is unchanged", path.display());
+        return Ok(());
+    }
+    let (old_masked, new_masked) = (masked(old), masked(new));
+    if old_masked == new_masked {
+        eprintln!("Only secrets change, which aren't shown");
+    } else {
+        super::print_diff(&path, &old_masked, &new_masked);
+    }
+    if !yes && !confirm(&format!("Save {}?", path.display()))? {
+        bail!("Not saved");
+    }
+    backup(&path)?;
+    atomic_write_private(&path, new.to_string())?;
+    eprintln!(
+        "{}",
+        style(format!("Saved {}", path.display())).bold().green()
+    );
+    Ok(())
+}
+
+/// Keep the previous config as `~/.heygpt.toml.bak`, only readable by the user as it holds the
+/// API key
+fn backup(path: &Path) -> Result<()> {
+    if path.exists() {
+        let backup = path.with_extension("toml.bak");
+        // Created anew, so that it doesn't keep the permissions of an older backup
+        match std::fs::remove_file(&backup) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        atomic_write_private(&backup, std::fs::read(path)?)?;
+    }
+    Ok(())
+}
+
+/// The config with the secrets hidden at any depth, to show it
+fn masked(config: &Document) -> String {
+    let mut config = config.clone();
+    for path in secrets(&config) {
+        let value = path
+            .iter()
+            .try_fold(config.as_item_mut(), |item, key| item.get_mut(key.get()))
+            .and_then(Item::as_value_mut);
+        if let Some(value) = value {
+            let decor = value.decor().clone();
+            *value = Value::from("********");
+            *value.decor_mut() = decor;
+        }
+    }
+    config.to_string()
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    let term = console::Term::stderr();
+    if !term.is_term() || !atty::is(atty::Stream::Stdin) {
+        bail!("Pass --yes to save without a terminal to confirm on");
+    }
+    term.write_str(&format!("{question} [y/N] "))?;
+    Ok(is_yes(&term.read_line()?))
+}
@@ -0,0 +1,331 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use log::debug;
+use serde::Deserialize;
+
+use crate::offline;
+use crate::storage::{atomic_write, cache_dir};
+use crate::Session;
+
+const SYSTEM_PROMPT: &str = "\
+You are an expert Rust programmer. Answer questions about the API of a crate using the provided \
+documentation from docs.rs, which matches the version of the crate the user depends on. Prefer \
+the documented API over your memory of other versions, and include short, compilable examples.";
+
+const DOCS_RS: &str = "https://docs.rs";
+
+/// Resolution of `latest` to a concrete version is cached for a day
+const LATEST_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Maximum characters of documentation to include in the prompt
+const MAX_DOCS_LEN: usize = 24 * 1024;
+
+/// Explain the API of a crate with documentation fetched from docs.rs,
+/// e.g. `heygpt crate tokio::select how to loop over two channels?`
+#[derive(Parser, Debug)]
+#[command(trailing_var_arg = true)]
+pub struct Args {
+    /// Path to the item, e.g. `tokio::sync::Mutex`
+    path: String,
+
+    /// Version of the crate (default: the version locked in ./Cargo.lock, otherwise the latest)
+    #[arg(long)]
+    version: Option<String>,
+
+    /// The question to ask (default: explain the usage of the item)
+    question: Vec<String>,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let mut segments = args.path.split("::").filter(|s| !s.is_empty());
+    let Some(krate) = segments.next() else {
+        bail!("Please specify a crate, e.g. `tokio::select`");
+    };
+    let krate = krate.replace('-', "_");
+    offline::check_url(&session.options, "docs host", DOCS_RS)?;
+    let segments: Vec<&str> = segments.collect();
+
+    let version = match args.version {
+        Some(v) => v,
+        None => match locked_version(&krate)? {
+            Some(v) => v,
+            None => latest_version(&krate).await?,
+        },
+    };
+    debug!("Using docs of {krate} {version}");
+
+    let docs = DocsRs {
+        client: reqwest::Client::new(),
+        krate: krate.clone(),
+        version: version.clone(),
+    };
+    let page = docs.find_page(&segments).await?;
+    let html = docs.get(&page).await?;
+    let mut text = html_to_text(main_content(&html));
+    if text.len() > MAX_DOCS_LEN {
+        let end = text.floor_char_boundary(MAX_DOCS_LEN);
+        text.truncate(end);
+        text.push_str("\n(truncated)");
+    }
+
+    let mut prompt = format!(
+        "Documentation of `{}` from {DOCS_RS}/{krate}/{version}/{krate}/{page}:\n\n{text}\n\n",
+        args.path
+    );
+    if args.question.is_empty() {
+        prompt.push_str(&format!(
+            "Explain how to use `{}` with examples.",
+            args.path
+        ));
+    } else {
+        prompt.push_str(&args.question.join(" "));
+    }
+
+    let system = super::system_prompt(
+        session,
+        &super::builtin_prompt(session, "crate", SYSTEM_PROMPT),
+    );
+    session.ask(Some(system), prompt).await?;
+    Ok(())
+}
+
+/// The version of a crate locked in `Cargo.lock` of the current directory
+fn locked_version(krate: &str) -> Result<Option<String>> {
+    #[derive(Deserialize)]
+    struct Lockfile {
+        #[serde(default)]
+        package: Vec<LockedPackage>,
+    }
+    #[derive(Deserialize)]
+    struct LockedPackage {
+        name: String,
+        version: String,
+    }
+
+    let Ok(content) = std::fs::read_to_string("Cargo.lock") else {
+        return Ok(None);
+    };
+    let lockfile: Lockfile = toml::from_str(&content)?;
+    // If multiple versions are locked, the last one is the newest
+    Ok(lockfile
+        .package
+        .into_iter()
+        .rfind(|p| p.name.replace('-', "_") == krate)
+        .map(|p| p.version))
+}
+
+/// Resolve the latest version of a crate via the redirect of docs.rs
+async fn latest_version(krate: &str) -> Result<String> {
+    let cache_file = docs_cache_dir().join(krate).join("latest");
+    if let Ok(metadata) = std::fs::metadata(&cache_file) {
+        let age = SystemTime::now()
+            .duration_since(metadata.modified()?)
+            .unwrap_or_default();
+        if age < LATEST_TTL {
+            return Ok(std::fs::read_to_string(&cache_file)?.trim().to_owned());
+        }
+    }
+
+    let url = format!("{DOCS_RS}/{krate}/latest/{krate}/");
+    let response = reqwest::get(&url).await?.error_for_status()?;
+    // e.g. https://docs.rs/tokio/1.33.0/tokio/
+    let Some(version) = response.url().path_segments().and_then(|mut s| s.nth(1)) else {
+        bail!("Unexpected URL from docs.rs: {}", response.url());
+    };
+    let version = version.to_owned();
+    atomic_write(&cache_file, &version)?;
+    Ok(version)
+}
+
+fn docs_cache_dir() -> PathBuf {
+    cache_dir().join("docs")
+}
+
+/// Pages of a crate of a specific version on docs.rs, cached locally
+struct DocsRs {
+    client: reqwest::Client,
+    krate: String,
+    version: String,
+}
+
+impl DocsRs {
+    /// Get a page by its path relative to the crate root, e.g. `sync/struct.Mutex.html`
+    async fn get(&self, page: &str) -> Result<String> {
+        let cache_file = docs_cache_dir()
+            .join(&self.krate)
+            .join(&self.version)
+            .join(page);
+        if let Ok(html) = std::fs::read_to_string(&cache_file) {
+            debug!("Loaded {} from cache", cache_file.display());
+            return Ok(html);
+        }
+
+        let url = format!(
+            "{DOCS_RS}/{}/{}/{}/{page}",
+            self.krate, self.version, self.krate
+        );
+        debug!("Fetching {url}");
+        let response = self.client.get(&url).send().await?;
+        if !response.status().is_success() {
+            bail!("Failed to fetch {url}: {}", response.status());
+        }
+        let html = response.text().await?;
+        atomic_write(&cache_file, &html)?;
+        Ok(html)
+    }
+
+    /// Find the page documenting the item at the given path below the crate root.
+    ///
+    /// If the last segment is not an item on its own, e.g. a method, the page of its parent
+    /// item is returned.
+    async fn find_page(&self, segments: &[&str]) -> Result<String> {
+        if segments.is_empty() {
+            return Ok("index.html".to_owned());
+        }
+
+        let all = self.get("all.html").await.context("Failed to list items")?;
+        let links = links(&all);
+        let find = |segments: &[&str]| -> Option<String> {
+            let (name, modules) = segments.split_last()?;
+            let prefix: String = modules.iter().map(|m| format!("{m}/")).collect();
+            let suffix = format!(".{name}.html");
+            links
+                .iter()
+                .find(|l| {
+                    l.strip_prefix(&prefix)
+                        .and_then(|l| l.strip_suffix(&suffix))
+                        .is_some_and(|kind| !kind.contains('/'))
+                })
+                .map(|l| l.to_string())
+        };
+
+        if let Some(page) = find(segments) {
+            return Ok(page);
+        }
+        // Modules are not listed in all.html
+        let module_page = format!("{}/index.html", segments.join("/"));
+        if self.get(&module_page).await.is_ok() {
+            return Ok(module_page);
+        }
+        if let Some(page) = find(&segments[..segments.len() - 1]) {
+            return Ok(page);
+        }
+        bail!(
+            "Item `{}` not found in {} {}",
+            segments.join("::"),
+            self.krate,
+            self.version
+        )
+    }
+}
+
+/// Targets of all `href` attributes in a HTML document
+fn links(html: &str) -> Vec<&str> {
+    html.split("href=\"")
+        .skip(1)
+        .filter_map(|s| s.split('"').next())
+        .collect()
+}
+
+/// The main content of a rustdoc page, without the navigation side bar
+fn main_content(html: &str) -> &str {
+    let start = html.find("id=\"main-content\"").unwrap_or(0);
+    let end = html[start..]
+        .find("</main>")
+        .map_or(html.len(), |end| start + end);
+    &html[start..end]
+}
+
+/// Convert rustdoc HTML to plain text, keeping code blocks as Markdown code fences
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_pre = false;
+    let mut skip_until: Option<String> = None;
+    let mut rest = html;
+
+    // Skip the remainder of the opening tag, e.g. `class="content">`
+    if let Some(end) = rest.find('>').filter(|_| !rest.starts_with('<')) {
+        rest = &rest[end + 1..];
+    }
+
+    while let Some(start) = rest.find('<') {
+        if skip_until.is_none() {
+            text.push_str(&decode_entities(&rest[..start]));
+        }
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let closing = tag.starts_with('/');
+
+        if let Some(until) = &skip_until {
+            if closing && &name == until {
+                skip_until = None;
+            }
+            continue;
+        }
+        match (name.as_str(), closing) {
+            ("script" | "style" | "button" | "nav" | "summary", false) => skip_until = Some(name),
+            ("pre", false) => {
+                in_pre = true;
+                text.push_str("\n```rust\n");
+            }
+            ("pre", true) => {
+                in_pre = false;
+                text.push_str("\n```\n");
+            }
+            ("code", _) if !in_pre => text.push('`'),
+            ("br", _) => text.push('\n'),
+            ("li", false) => text.push_str("\n- "),
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => text.push_str("\n\n"),
+            (
+                "p" | "div" | "section" | "details" | "tr" | "ul" | "ol" | "h1" | "h2" | "h3"
+                | "h4" | "h5" | "h6",
+                true,
+            ) => text.push('\n'),
+            _ => {}
+        }
+    }
+    if skip_until.is_none() {
+        text.push_str(&decode_entities(rest));
+    }
+
+    // Remove anchors and collapse blank lines
+    let text = text.replace('§', "");
+    let mut result = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result.trim().to_owned()
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
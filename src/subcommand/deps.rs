@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use log::debug;
+use serde::Deserialize;
+
+use crate::model::ResponseFormat;
+use crate::Session;
+
+const SYSTEM_PROMPT: &str = "\
+You are an expert in the Rust ecosystem and open source licensing. Given the dependencies of a \
+Cargo project, summarize what the notable dependencies are used for, the licenses involved and \
+whether they are compatible with each other and with the license of the project, and potential \
+concerns such as unmaintained or deprecated crates, duplicated functionality, multiple versions \
+of the same crate or an unusually large dependency tree. Be concise.";
+
+const JSON_PROMPT: &str = "\
+Respond with a JSON object only, using this structure:
+{
+  \"summary\": string,
+  \"notable_dependencies\": [{\"name\": string, \"version\": string, \"license\": string or null, \"purpose\": string}],
+  \"licenses\": [{\"license\": string, \"crates\": number, \"notes\": string}],
+  \"concerns\": [{\"severity\": \"low\" | \"medium\" | \"high\", \"crates\": [string], \"description\": string}]
+}";
+
+/// Summarize dependencies, licenses and potential concerns of a Cargo project
+#[derive(Parser, Debug)]
+#[command(trailing_var_arg = true)]
+pub struct Args {
+    /// Path to Cargo.toml
+    #[arg(long, default_value = "Cargo.toml")]
+    manifest_path: PathBuf,
+
+    /// Output the summary as a JSON object
+    #[arg(long)]
+    json: bool,
+
+    /// Additional instructions, e.g. "focus on licenses"
+    instructions: Vec<String>,
+}
+
+/// Output of `cargo metadata`, only the fields used here
+#[derive(Deserialize, Debug)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Package {
+    id: String,
+    name: String,
+    version: String,
+    license: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Dependency {
+    name: String,
+    kind: Option<String>,
+}
+
+/// Content of `Cargo.lock`, only the fields used here
+#[derive(Deserialize, Debug)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let mut prompt = describe_dependencies(&args.manifest_path)?;
+    if !args.instructions.is_empty() {
+        write!(prompt, "\n{}", args.instructions.join(" "))?;
+    }
+
+    let mut system = super::system_prompt(
+        session,
+        &super::builtin_prompt(session, "deps", SYSTEM_PROMPT),
+    );
+    if args.json {
+        system = format!("{system}\n\n{JSON_PROMPT}");
+        session.response_format = Some(ResponseFormat::json_object());
+        // The JSON object is only useful once complete
+        session.options.stream = false;
+    }
+    session.ask(Some(system), prompt).await?;
+    Ok(())
+}
+
+/// Describe the dependencies with `cargo metadata`, falling back to `Cargo.lock` which lacks
+/// licenses and descriptions
+fn describe_dependencies(manifest_path: &Path) -> Result<String> {
+    match cargo_metadata(manifest_path) {
+        Ok(metadata) => describe_metadata(&metadata),
+        Err(err) => {
+            debug!("cargo metadata failed, reading Cargo.lock instead: {err}");
+            let lockfile = manifest_path.with_file_name("Cargo.lock");
+            let content = std::fs::read_to_string(&lockfile)
+                .with_context(|| format!("Failed to read {}", lockfile.display()))?;
+            describe_lockfile(&toml::from_str(&content)?)
+        }
+    }
+}
+
+fn cargo_metadata(manifest_path: &Path) -> Result<Metadata> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--manifest-path"])
+        .arg(manifest_path)
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+fn describe_metadata(metadata: &Metadata) -> Result<String> {
+    let members: Vec<&Package> = metadata
+        .packages
+        .iter()
+        .filter(|p| metadata.workspace_members.contains(&p.id))
+        .collect();
+
+    // Direct dependencies with their kind, e.g. `dev`
+    let mut direct: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for dep in members.iter().flat_map(|p| &p.dependencies) {
+        direct
+            .entry(dep.name.as_str())
+            .or_default()
+            .insert(dep.kind.as_deref().unwrap_or("normal"));
+    }
+
+    let mut description = String::from("Workspace members:\n");
+    for p in &members {
+        let license = p.license.as_deref().unwrap_or("unknown license");
+        writeln!(description, "- {} {} ({license})", p.name, p.version)?;
+    }
+
+    writeln!(description, "\nDirect dependencies:")?;
+    let dependencies = || {
+        metadata
+            .packages
+            .iter()
+            .filter(|p| !metadata.workspace_members.contains(&p.id))
+    };
+    for p in dependencies().filter(|p| direct.contains_key(p.name.as_str())) {
+        let kinds = &direct[p.name.as_str()];
+        let license = p.license.as_deref().unwrap_or("unknown license");
+        write!(description, "- {} {} ({license})", p.name, p.version)?;
+        if !kinds.contains("normal") {
+            let kinds: Vec<_> = kinds.iter().copied().collect();
+            write!(description, " [{}]", kinds.join(", "))?;
+        }
+        if let Some(d) = &p.description {
+            write!(
+                description,
+                ": {}",
+                d.split_whitespace().collect::<Vec<_>>().join(" ")
+            )?;
+        }
+        description.push('\n');
+    }
+
+    writeln!(description, "\nTransitive dependencies:")?;
+    for p in dependencies().filter(|p| !direct.contains_key(p.name.as_str())) {
+        let license = p.license.as_deref().unwrap_or("unknown license");
+        writeln!(description, "- {} {} ({license})", p.name, p.version)?;
+    }
+
+    let mut licenses: BTreeMap<&str, usize> = BTreeMap::new();
+    for p in dependencies() {
+        *licenses
+            .entry(p.license.as_deref().unwrap_or("unknown"))
+            .or_default() += 1;
+    }
+    writeln!(description, "\nNumber of crates by license:")?;
+    for (license, count) in licenses {
+        writeln!(description, "- {license}: {count}")?;
+    }
+    Ok(description)
+}
+
+fn describe_lockfile(lockfile: &Lockfile) -> Result<String> {
+    let mut description = String::from("Locked packages (licenses unknown):\n");
+    for p in &lockfile.package {
+        write!(description, "- {} {}", p.name, p.version)?;
+        if p.source.is_none() {
+            write!(description, " (local)")?;
+        }
+        description.push('\n');
+    }
+    Ok(description)
+}
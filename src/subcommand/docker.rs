@@ -0,0 +1,154 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+
+use crate::Session;
+
+const SYSTEM_PROMPT: &str = "\
+You are an expert in containerizing applications with Docker. Write production-ready \
+Dockerfiles: use multi-stage builds where they reduce the image size, pin base image versions, \
+order layers to maximize build cache reuse, run as a non-root user and avoid leaking secrets \
+into the image. Explain every non-obvious instruction with a short comment in the file itself. \
+Output the files in code blocks, each preceded by its file name.";
+
+const CRITIQUE_PROMPT: &str = "\
+You are an expert in containerizing applications with Docker. Review the given Dockerfile for \
+correctness, security (root user, secrets, unpinned or untrusted images), image size, build \
+cache efficiency and readability. List the issues ordered by severity, each with a concrete \
+fix, and finish with the improved Dockerfile.";
+
+/// Build files which reveal the language, toolchain and entry points of a project
+const BUILD_FILES: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "go.mod",
+    "requirements.txt",
+    "pyproject.toml",
+    "Pipfile",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "Gemfile",
+    "composer.json",
+    "mix.exs",
+    "Makefile",
+    "Dockerfile",
+    "docker-compose.yml",
+    "compose.yaml",
+    ".dockerignore",
+];
+
+/// Maximum bytes of each build file to include in the prompt
+const MAX_FILE_SIZE: usize = 4096;
+
+/// Maximum number of entries of the directory listing to include in the prompt
+const MAX_LISTING: usize = 100;
+
+/// Generate a Dockerfile for the project in the current directory, or critique an existing one
+#[derive(Parser, Debug)]
+#[command(trailing_var_arg = true)]
+pub struct Args {
+    /// An existing Dockerfile to improve or critique
+    #[arg(long)]
+    file: Option<PathBuf>,
+
+    /// Critique the Dockerfile given by --file instead of generating one
+    #[arg(long, requires = "file")]
+    critique: bool,
+
+    /// Also generate a docker-compose.yml
+    #[arg(long, conflicts_with = "critique")]
+    compose: bool,
+
+    /// The project directory to scan
+    #[arg(long, default_value = ".")]
+    dir: PathBuf,
+
+    /// Additional instructions, e.g. "containerize this project"
+    instructions: Vec<String>,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let dockerfile = match &args.file {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    let mut prompt = String::new();
+    let system = if args.critique {
+        let Some(dockerfile) = dockerfile else {
+            bail!("--critique requires --file");
+        };
+        writeln!(prompt, "Please critique this Dockerfile:")?;
+        writeln!(prompt, "```Dockerfile\n{}\n```", dockerfile.trim_end())?;
+        super::builtin_prompt(session, "docker-critique", CRITIQUE_PROMPT)
+    } else {
+        if args.compose {
+            writeln!(
+                prompt,
+                "Please write a Dockerfile and a docker-compose.yml for this project."
+            )?;
+        } else {
+            writeln!(prompt, "Please write a Dockerfile for this project.")?;
+        }
+        if let Some(dockerfile) = dockerfile {
+            writeln!(prompt, "Start from the existing Dockerfile:")?;
+            writeln!(prompt, "```Dockerfile\n{}\n```", dockerfile.trim_end())?;
+        }
+        prompt.push('\n');
+        prompt.push_str(&describe_project(&args.dir)?);
+        super::builtin_prompt(session, "docker", SYSTEM_PROMPT)
+    };
+
+    if !args.instructions.is_empty() {
+        write!(prompt, "\n{}", args.instructions.join(" "))?;
+    }
+
+    let system = super::system_prompt(session, &system);
+    session.ask(Some(system), prompt).await?;
+    Ok(())
+}
+
+/// Describe the project by its file listing and the content of its build files
+fn describe_project(dir: &Path) -> Result<String> {
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let mut name = e.file_name().to_string_lossy().into_owned();
+            if e.path().is_dir() {
+                name.push('/');
+            }
+            name
+        })
+        .filter(|name| !name.starts_with('.') || BUILD_FILES.contains(&name.as_str()))
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let mut description = String::from("Files in the project root:\n");
+    for name in entries.iter().take(MAX_LISTING) {
+        writeln!(description, "- {name}")?;
+    }
+    if entries.len() > MAX_LISTING {
+        writeln!(description, "- ... ({} more)", entries.len() - MAX_LISTING)?;
+    }
+
+    for name in BUILD_FILES
+        .iter()
+        .filter(|name| entries.contains(&name.to_string()))
+    {
+        let content = std::fs::read(dir.join(name))?;
+        let truncated = content.len() > MAX_FILE_SIZE;
+        let content = String::from_utf8_lossy(&content[..content.len().min(MAX_FILE_SIZE)]);
+        writeln!(description, "\n{name}:\n```\n{}\n```", content.trim_end())?;
+        if truncated {
+            writeln!(description, "(truncated)")?;
+        }
+    }
+    Ok(description)
+}
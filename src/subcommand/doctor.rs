@@ -0,0 +1,264 @@
+use std::time::Instant;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use console::style;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde_json::{json, Value};
+
+use crate::model::{Message, Request, WrappedApiError};
+use crate::provider::{Decoder, Provider};
+use crate::Session;
+
+/// Check the configuration against the API: key, endpoint, model, streaming and tool calling.
+/// Sends three tiny completions, which cost a few tokens.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Skip the completion requests and only query the model list
+    #[arg(long)]
+    no_completion: bool,
+}
+
+enum Status {
+    Ok,
+    Warning,
+    Failed,
+    Skipped,
+}
+
+/// Outcomes of the checks, printed as they come
+#[derive(Default)]
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    fn check(&mut self, status: Status, what: &str, details: impl AsRef<str>) {
+        let mark = match status {
+            Status::Ok => style("✓").bold().green(),
+            Status::Warning => style("!").bold().yellow(),
+            Status::Skipped => style("-").dim(),
+            Status::Failed => {
+                self.failed += 1;
+                style("✗").bold().red()
+            }
+        };
+        let details = details.as_ref();
+        if details.is_empty() {
+            println!("{mark} {what}");
+        } else {
+            println!("{mark} {what}: {details}");
+        }
+    }
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let options = &session.options;
+    let provider = options.provider;
+    let client = Client::new();
+    let post = |stream: bool| {
+        client
+            .post(provider.url(&options.api_base_url, &options.model, stream))
+            .headers(provider.headers(&options.api_key))
+    };
+    let mut report = Report::default();
+
+    let response = client
+        .get(provider.models_url(&options.api_base_url))
+        .headers(provider.headers(&options.api_key))
+        .send()
+        .await;
+    match response {
+        Err(err) => {
+            report.check(
+                Status::Failed,
+                "Endpoint",
+                format!(
+                    "can't reach {} ({err}). Check api_base_url and your network or proxy settings",
+                    options.api_base_url
+                ),
+            );
+            bail!("The endpoint is unreachable");
+        }
+        Ok(r) if matches!(r.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+            report.check(Status::Ok, "Endpoint", &options.api_base_url);
+            report.check(
+                Status::Failed,
+                "API key",
+                format!(
+                    "rejected. Check {} or api_key in ~/.heygpt.toml",
+                    provider.api_key_env().unwrap_or("api_key")
+                ),
+            );
+            bail!("The API key is invalid");
+        }
+        Ok(r) if !r.status().is_success() => {
+            report.check(Status::Ok, "Endpoint", &options.api_base_url);
+            report.check(
+                Status::Warning,
+                "Models",
+                format!(
+                    "listing models failed with {}, some providers don't support it",
+                    r.status()
+                ),
+            );
+        }
+        Ok(r) => {
+            report.check(Status::Ok, "Endpoint", &options.api_base_url);
+            report.check(Status::Ok, "API key", "");
+            let text = r.text().await.unwrap_or_default();
+            match provider.parse_models(&text) {
+                Ok(ids) if ids.contains(&options.model) => {
+                    report.check(Status::Ok, "Model", &options.model);
+                }
+                Ok(mut ids) => {
+                    ids.sort();
+                    report.check(
+                        Status::Failed,
+                        "Model",
+                        format!(
+                            "{} isn't offered by the endpoint. Set --model or model in ~/.heygpt.toml to one of: {}",
+                            options.model,
+                            ids.join(", ")
+                        ),
+                    );
+                }
+                Err(err) => report.check(
+                    Status::Warning,
+                    "Models",
+                    format!("unexpected model list ({err})"),
+                ),
+            }
+        }
+    }
+
+    if args.no_completion {
+        return finish(report);
+    }
+
+    let mut request = Request {
+        model: options.model.clone(),
+        messages: vec![Message {
+            role: "user".to_owned(),
+            content: "Reply with OK.".to_owned(),
+            meta: None,
+        }],
+        stream: false,
+        temperature: None,
+        top_p: None,
+        max_tokens: Some(5),
+        response_format: None,
+        stream_options: None,
+        images: Default::default(),
+    };
+    let start = Instant::now();
+    match send(post(false).json(&provider.body(&request))).await {
+        Ok(text) => match provider.parse_reply(&text) {
+            Ok(_) => report.check(
+                Status::Ok,
+                "Completion",
+                format!("{} ms", start.elapsed().as_millis()),
+            ),
+            Err(err) => report.check(
+                Status::Failed,
+                "Completion",
+                format!("unexpected response ({err})"),
+            ),
+        },
+        Err(err) => report.check(Status::Failed, "Completion", err),
+    }
+
+    request.stream = true;
+    match send(post(true).json(&provider.body(&request))).await {
+        Ok(text) => {
+            let mut decoder = Decoder::new(provider);
+            decoder.push(text.as_bytes());
+            let mut events: Vec<String> = std::iter::from_fn(|| decoder.next_event()).collect();
+            events.extend(decoder.finish());
+            if !events.is_empty() && events.iter().all(|e| provider.parse_chunk(e).is_ok()) {
+                report.check(Status::Ok, "Streaming", "");
+            } else {
+                report.check(
+                    Status::Failed,
+                    "Streaming",
+                    "the response isn't a stream of events. Use --stream=false or stream = false in ~/.heygpt.toml",
+                );
+            }
+        }
+        Err(err) => report.check(
+            Status::Failed,
+            "Streaming",
+            format!("{err}. Use --stream=false or stream = false in ~/.heygpt.toml"),
+        ),
+    }
+
+    // Tools are only sent in the format of OpenAI
+    if provider != Provider::Openai {
+        report.check(
+            Status::Skipped,
+            "Tool calling",
+            format!("only checked with the openai provider, not {provider:?}").to_lowercase(),
+        );
+        return finish(report);
+    }
+    let mut tools_body = provider.body(&Request {
+        stream: false,
+        max_tokens: Some(50),
+        ..request
+    });
+    tools_body["tools"] = json!([{
+        "type": "function",
+        "function": {
+            "name": "get_time",
+            "description": "Get the current time",
+            "parameters": {"type": "object", "properties": {}},
+        },
+    }]);
+    tools_body["tool_choice"] = json!({"type": "function", "function": {"name": "get_time"}});
+    tools_body["messages"] = json!([{"role": "user", "content": "What time is it?"}]);
+    let response = send(post(false).json(&tools_body))
+        .await
+        .and_then(|text| serde_json::from_str::<Value>(&text).map_err(|err| err.to_string()));
+    match response {
+        Ok(response) if !response["choices"][0]["message"]["tool_calls"].is_null() => {
+            report.check(Status::Ok, "Tool calling", "");
+        }
+        Ok(_) => report.check(
+            Status::Warning,
+            "Tool calling",
+            "accepted, but the model didn't call the tool",
+        ),
+        Err(err) => report.check(
+            Status::Warning,
+            "Tool calling",
+            format!("unsupported ({err})"),
+        ),
+    }
+
+    finish(report)
+}
+
+fn finish(report: Report) -> Result<()> {
+    if report.failed > 0 {
+        bail!("Failed checks: {}", report.failed);
+    }
+    Ok(())
+}
+
+/// Send a request, returning the body of the response or a readable error
+async fn send(req: RequestBuilder) -> Result<String, String> {
+    let response = req.send().await.map_err(|err| err.to_string())?;
+    let status = response.status();
+    let text = response.text().await.map_err(|err| err.to_string())?;
+    if !status.is_success() {
+        return Err(error_message(status, &text));
+    }
+    Ok(text)
+}
+
+fn error_message(status: StatusCode, body: &str) -> String {
+    match serde_json::from_str::<WrappedApiError>(body) {
+        Ok(e) => format!("{status}: {}", e.error.message),
+        Err(_) => status.to_string(),
+    }
+}
@@ -0,0 +1,287 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use console::style;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::model::Message;
+use crate::Session;
+
+const GRADER_PROMPT: &str = "\
+You are grading the response of an AI assistant against a rubric. Judge only whether the \
+response satisfies the rubric, not its style. Respond with a JSON object: \
+{\"pass\": boolean, \"reason\": string}";
+
+/// Run an evaluation suite of prompts against models and report which expectations fail
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The suite in TOML, or YAML with a `.yaml` or `.yml` extension, see README for the format
+    suite: PathBuf,
+
+    /// Print the response of every case
+    #[arg(long)]
+    show_output: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Suite {
+    /// Models to run each case with (default: the model from options)
+    #[serde(default)]
+    models: Vec<String>,
+
+    /// Model grading the rubrics (default: the model from options)
+    grader: Option<String>,
+
+    /// System prompt for all cases
+    system: Option<String>,
+
+    cases: Vec<Case>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    name: String,
+    /// Overrides the system prompt of the suite
+    system: Option<String>,
+    prompt: String,
+    #[serde(default)]
+    expect: Vec<Expectation>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Expectation {
+    /// The response matches the regular expression
+    Regex(String),
+    /// The response doesn't match the regular expression
+    NotRegex(String),
+    /// The response is JSON conforming to the schema
+    JsonSchema(Value),
+    /// The response satisfies the rubric, as judged by the grader model
+    Rubric(String),
+}
+
+#[derive(Deserialize, Debug)]
+struct Grade {
+    pass: bool,
+    reason: String,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let content = std::fs::read_to_string(&args.suite)
+        .with_context(|| format!("Failed to read {}", args.suite.display()))?;
+    let suite = parse_suite(&args.suite, &content)
+        .with_context(|| format!("Invalid suite {}", args.suite.display()))?;
+
+    let default_model = session.options.model.clone();
+    let models = if suite.models.is_empty() {
+        vec![default_model.clone()]
+    } else {
+        suite.models.clone()
+    };
+    let grader = suite
+        .grader
+        .clone()
+        .unwrap_or_else(|| default_model.clone());
+
+    let (mut passed, mut failed) = (0, 0);
+    for case in &suite.cases {
+        for model in &models {
+            session.options.model = model.clone();
+            session.messages.clear();
+            if let Some(system) = case.system.as_ref().or(suite.system.as_ref()) {
+                session.messages.push(Message {
+                    role: "system".to_string(),
+                    content: system.clone(),
+                    meta: None,
+                });
+            }
+            session.messages.push(Message {
+                role: "user".to_string(),
+                content: case.prompt.clone(),
+                meta: None,
+            });
+            let response = session.complete().await?.content;
+
+            session.options.model = grader.clone();
+            let mut failures = Vec::new();
+            for expectation in &case.expect {
+                if let Err(reason) = check(session, case, expectation, &response).await? {
+                    failures.push(reason);
+                }
+            }
+
+            if failures.is_empty() {
+                passed += 1;
+                println!("{} {} [{model}]", style("PASS").bold().green(), case.name);
+            } else {
+                failed += 1;
+                println!("{} {} [{model}]", style("FAIL").bold().red(), case.name);
+                for reason in &failures {
+                    println!("     - {reason}");
+                }
+            }
+            if args.show_output {
+                println!("{}", style(response.trim()).dim());
+            }
+        }
+    }
+    session.options.model = default_model;
+
+    println!(
+        "\n{} passed, {} failed",
+        style(passed).bold().green(),
+        style(failed).bold().red()
+    );
+    if failed > 0 {
+        bail!("{failed} of {} runs failed", passed + failed);
+    }
+    Ok(())
+}
+
+/// Parse a suite in YAML or TOML, according to the extension of its file
+fn parse_suite(path: &Path, content: &str) -> Result<Suite> {
+    let yaml = path
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+    if !yaml {
+        return Ok(toml::from_str(content)?);
+    }
+    // Expectations are written `- regex: ...` as in TOML, rather than with YAML tags
+    #[cfg(feature = "yaml")]
+    return Ok(serde_yaml::with::singleton_map_recursive::deserialize(
+        serde_yaml::Deserializer::from_str(content),
+    )?);
+    #[cfg(not(feature = "yaml"))]
+    bail!("heygpt was built without the `yaml` feature, needed for YAML suites")
+}
+
+/// Check an expectation. The outer error is for failures of the check itself, while the inner
+/// one explains why the response doesn't meet the expectation.
+async fn check(
+    session: &mut Session,
+    case: &Case,
+    expectation: &Expectation,
+    response: &str,
+) -> Result<Result<(), String>> {
+    Ok(match expectation {
+        Expectation::Regex(re) => {
+            if Regex::new(re)?.is_match(response) {
+                Ok(())
+            } else {
+                Err(format!("doesn't match /{re}/"))
+            }
+        }
+        Expectation::NotRegex(re) => {
+            if Regex::new(re)?.is_match(response) {
+                Err(format!("matches /{re}/"))
+            } else {
+                Ok(())
+            }
+        }
+        Expectation::JsonSchema(schema) => match serde_json::from_str(strip_code_fence(response)) {
+            Ok(value) => validate(schema, &value, "$"),
+            Err(err) => Err(format!("invalid JSON: {err}")),
+        },
+        Expectation::Rubric(rubric) => {
+            session.messages.clear();
+            session.messages.push(Message {
+                role: "system".to_string(),
+                content: super::builtin_prompt(session, "eval", GRADER_PROMPT),
+                meta: None,
+            });
+            session.messages.push(Message {
+                role: "user".to_string(),
+                content: format!(
+                    "Prompt:\n{}\n\nResponse:\n{response}\n\nRubric:\n{rubric}",
+                    case.prompt
+                ),
+                meta: None,
+            });
+            let (_, grade) = session.complete_json::<Grade>().await?;
+            if grade.pass {
+                Ok(())
+            } else {
+                Err(format!("rubric \"{rubric}\": {}", grade.reason))
+            }
+        }
+    })
+}
+
+/// Models tend to wrap JSON in a Markdown code block
+fn strip_code_fence(s: &str) -> &str {
+    let s = s.trim();
+    match s.strip_prefix("```") {
+        Some(rest) => {
+            let rest = rest.split_once('\n').map_or("", |(_, r)| r);
+            rest.strip_suffix("```").unwrap_or(rest).trim()
+        }
+        None => s,
+    }
+}
+
+/// Validate a value against a JSON schema. Only a subset of JSON schema is supported:
+/// `type`, `enum`, `required`, `properties`, `additionalProperties: false` and `items`.
+fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    if let Some(ty) = schema.get("type") {
+        let types: Vec<&str> = match ty {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        let matches = |t: &str| match t {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => false,
+        };
+        if !types.iter().any(|t| matches(t)) {
+            return Err(format!("{path}: expected {}", types.join(" or ")));
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            return Err(format!(
+                "{path}: {value} is not one of {}",
+                Value::from(options.clone())
+            ));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    return Err(format!("{path}: missing property \"{key}\""));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, v) in object {
+            match properties.and_then(|p| p.get(key)) {
+                Some(s) => validate(s, v, &format!("{path}.{key}"))?,
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    return Err(format!("{path}: unexpected property \"{key}\""));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(s)) = (value, schema.get("items")) {
+        for (i, v) in items.iter().enumerate() {
+            validate(s, v, &format!("{path}[{i}]"))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use console::style;
+
+use crate::sessions::{self, format_time, Retention, SavedSession};
+use crate::Session;
+
+/// List, export, migrate and prune saved interactive sessions
+#[derive(Parser, Debug)]
+#[command(disable_help_subcommand = true)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Action {
+    /// List saved sessions, most recent first
+    List {
+        /// Only sessions with this tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Print a saved session as Markdown
+    Export {
+        /// ID of the session, as shown by `list`
+        id: String,
+
+        /// Include the content of attached files
+        #[arg(long)]
+        with_attachments: bool,
+    },
+    /// Convert all saved sessions to `session_format` from the config file
+    Migrate,
+    /// Remove the oldest sessions exceeding the limits of `[retention]` in the config file
+    Prune {
+        /// Keep at most N sessions
+        #[arg(long, value_name = "N")]
+        max_sessions: Option<usize>,
+
+        /// Remove sessions not updated for more than N days
+        #[arg(long, value_name = "N")]
+        max_age_days: Option<u64>,
+
+        /// Keep at most N MB of sessions and attachments
+        #[arg(long, value_name = "N")]
+        max_size_mb: Option<u64>,
+    },
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    match args.action {
+        Action::List { tag } => {
+            for s in sessions::list()? {
+                if tag.as_ref().is_some_and(|tag| !s.tags.contains(tag)) {
+                    continue;
+                }
+                let tags = if s.tags.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", s.tags.join(", "))
+                };
+                println!(
+                    "{}  {}{}  {}",
+                    style(&s.id).bold(),
+                    format_time(s.updated),
+                    style(tags).cyan(),
+                    s.title
+                );
+            }
+        }
+        Action::Export {
+            id,
+            with_attachments,
+        } => print!("{}", export(&SavedSession::load(&id)?, with_attachments)?),
+        Action::Migrate => {
+            let format = session.options.session_format;
+            let mut converted = 0;
+            for summary in sessions::list()? {
+                if !summary.is_in(format) {
+                    SavedSession::load(&summary.id)?.write(format)?;
+                    converted += 1;
+                }
+            }
+            eprintln!("Converted {converted} sessions");
+        }
+        Action::Prune {
+            max_sessions,
+            max_age_days,
+            max_size_mb,
+        } => {
+            let config = &session.options.retention;
+            let retention = Retention {
+                max_sessions: max_sessions.or(config.max_sessions),
+                max_age_days: max_age_days.or(config.max_age_days),
+                max_size_mb: max_size_mb.or(config.max_size_mb),
+            };
+            if retention.is_unlimited() {
+                bail!("No limits, set them with the options or [retention] in ~/.heygpt.toml");
+            }
+            let removed = sessions::prune(&retention)?;
+            eprintln!("Removed {removed} sessions");
+        }
+    }
+    Ok(())
+}
+
+fn export(session: &SavedSession, with_attachments: bool) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "# {}\n", session.title())?;
+    writeln!(out, "- Created: {} UTC", format_time(session.created))?;
+    if !session.tags.is_empty() {
+        writeln!(out, "- Tags: {}", session.tags.join(", "))?;
+    }
+    for message in &session.messages {
+        match &message.meta {
+            Some(meta) => writeln!(out, "\n## {} ({meta})\n", message.role)?,
+            None => writeln!(out, "\n## {}\n", message.role)?,
+        }
+        let content = if with_attachments {
+            session.content(message)?
+        } else {
+            // Keep exports small by naming attachments instead of including them
+            session
+                .attachments
+                .iter()
+                .fold(message.content.clone(), |c, r| {
+                    c.replace(&r.marker(), &format!("(attached: {})", r.name))
+                })
+        };
+        writeln!(out, "{}", content.trim_end())?;
+    }
+    Ok(out)
+}
@@ -0,0 +1,62 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::Session;
+
+const SYSTEM_PROMPT: &str = "\
+You are an experienced site reliability engineer helping to troubleshoot Kubernetes clusters \
+and other production systems. The user may provide the output of commands such as \
+`kubectl describe`, `kubectl logs` or `kubectl get events`. Identify the most likely root cause \
+first, point out the exact lines in the output that support it, and list further checks if the \
+evidence is inconclusive. Be concise.";
+
+const RUN_SYSTEM_PROMPT: &str = "\
+For each remediation step, propose the exact `kubectl` command in a separate code block, \
+followed by a short explanation of what it does and its risks. Prefer read-only and reversible \
+commands, and clearly mark commands that modify or delete resources. Never claim that you \
+have run any command: the user will review and run them manually.";
+
+/// Troubleshoot Kubernetes and other ops issues,
+/// e.g. `kubectl describe pod foo | heygpt ops "why is it crashlooping?"`
+#[derive(Parser, Debug)]
+#[command(trailing_var_arg = true, disable_help_subcommand = true)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Option<Action>,
+
+    /// The question to ask. Input from stdin is attached as context
+    question: Vec<String>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Action {
+    /// Also propose kubectl commands for remediation. They are never run automatically
+    Run {
+        /// The question to ask. Input from stdin is attached as context
+        #[arg(trailing_var_arg = true)]
+        question: Vec<String>,
+    },
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let (question, propose_commands) = match args.action {
+        Some(Action::Run { question }) => (question, true),
+        None => (args.question, false),
+    };
+
+    let mut prompt = question.join(" ");
+    if let Some(input) = session.read_stdin()?.filter(|s| !s.trim().is_empty()) {
+        prompt = format!("{prompt}\n\n```\n{}\n```", input.trim_end());
+    }
+    if prompt.trim().is_empty() {
+        bail!("Please describe the problem or pipe in some command output");
+    }
+
+    let mut system = super::builtin_prompt(session, "ops", SYSTEM_PROMPT);
+    if propose_commands {
+        system = format!("{system}\n\n{RUN_SYSTEM_PROMPT}");
+    }
+    let system = super::system_prompt(session, &system);
+    session.ask(Some(system), prompt).await?;
+    Ok(())
+}
@@ -0,0 +1,722 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+
+use crate::i18n::is_yes;
+use crate::Session;
+
+/// Maximum size of the files searched for the definitions of the code block
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Directories skipped when the working tree isn't a git repository
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules", "vendor", "dist", "build"];
+
+/// Keywords introducing a definition, matched by kind and name against the working tree
+const DEFINITION_KINDS: &[&str] = &[
+    "fn",
+    "struct",
+    "enum",
+    "union",
+    "trait",
+    "impl",
+    "mod",
+    "macro_rules!",
+    "def",
+    "class",
+    "func",
+    "function",
+    "interface",
+    "type",
+];
+
+/// Keywords allowed before the kind of a definition
+const MODIFIERS: &[&str] = &[
+    "pub",
+    "async",
+    "unsafe",
+    "const",
+    "extern",
+    "\"C\"",
+    "default",
+    "export",
+    "abstract",
+    "static",
+    "public",
+    "private",
+    "protected",
+];
+
+/// Apply a code block copied from a chat in the browser to the working tree.
+///
+/// The code block is read from the clipboard, or from stdin if redirected. Each definition in
+/// it, e.g. a function, replaces the definition of the same kind and name in the file it
+/// belongs to, found by the path in the info string or the first comment of the block, or by
+/// searching the working tree. The changes are shown as a diff before they are applied.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The file to patch, instead of finding it
+    #[arg(long)]
+    file: Option<PathBuf>,
+
+    /// The working tree to search
+    #[arg(long, default_value = ".")]
+    dir: PathBuf,
+
+    /// Only show the diff
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Apply without asking
+    #[arg(long, short, conflicts_with = "dry_run")]
+    yes: bool,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let text = match session.read_stdin()? {
+        Some(text) if !text.trim().is_empty() => text,
+        _ => read_clipboard()?,
+    };
+    let snippet = Snippet::parse(&text);
+    if snippet.code.trim().is_empty() {
+        bail!("The code block is empty");
+    }
+
+    let hinted = match (&args.file, &snippet.path) {
+        (Some(file), _) => Some(file.clone()),
+        (None, Some(path)) => find_path(&args.dir, path)?,
+        (None, None) => None,
+    };
+    let (path, old) = match hinted {
+        Some(path) => {
+            let old = match std::fs::read_to_string(&path) {
+                Ok(old) => old,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(err) => {
+                    return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+                }
+            };
+            (path, old)
+        }
+        None => find_file(&args.dir, &snippet.code)?,
+    };
+    let new = patch(&old, &snippet.code);
+    if new == old {
+        println!("{} is up to date", path.display());
+        return Ok(());
+    }
+
+    super::print_diff(&path, &old, &new);
+    if args.dry_run {
+        return Ok(());
+    }
+    if !args.yes && !confirm(&path)? {
+        bail!("Not applied");
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, new).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Patched {}", path.display());
+    Ok(())
+}
+
+/// The first code block of the copied text, with the path of its file if named
+#[derive(Debug)]
+struct Snippet {
+    path: Option<String>,
+    code: String,
+}
+
+impl Snippet {
+    fn parse(text: &str) -> Self {
+        let mut lines = text.lines();
+        let mut info = None;
+        let mut code = Vec::new();
+        for line in lines.by_ref() {
+            let trimmed = line.trim_start();
+            let c = trimmed.chars().next().unwrap_or(' ');
+            let len = trimmed.chars().take_while(|&ch| ch == c).count();
+            if (c == '`' || c == '~') && len >= 3 {
+                info = Some((c, len, trimmed[len..].trim().to_owned()));
+                break;
+            }
+        }
+        let path = match &info {
+            Some((c, len, info)) => {
+                for line in lines {
+                    let trimmed = line.trim();
+                    if trimmed.chars().take_while(|ch| ch == c).count() >= *len
+                        && trimmed.trim_start_matches(*c).is_empty()
+                    {
+                        break;
+                    }
+                    code.push(line);
+                }
+                info.split_whitespace().find_map(path_in_info)
+            }
+            // Not fenced: the text is the code
+            None => {
+                code = text.lines().collect();
+                None
+            }
+        };
+        // A comment naming the file, e.g. `// src/main.rs`, isn't part of the code
+        let path = match (path, code.first().and_then(|line| path_in_comment(line))) {
+            (None, Some(path)) => {
+                code.remove(0);
+                Some(path)
+            }
+            (path, _) => path,
+        };
+        Self {
+            path,
+            code: code.join("\n"),
+        }
+    }
+}
+
+/// A path in the info string of a fence, e.g. `src/main.rs` in ```` ```rust src/main.rs ````
+fn path_in_info(word: &str) -> Option<String> {
+    let word = word
+        .trim_start_matches("title=")
+        .trim_start_matches("file=")
+        .trim_matches(['"', '\'']);
+    looks_like_path(word).then(|| word.to_owned())
+}
+
+/// A path in a comment on the first line, e.g. `// File: src/main.rs` or `# app.py`
+fn path_in_comment(line: &str) -> Option<String> {
+    let comment = ["//", "#", "--", "<!--"]
+        .iter()
+        .find_map(|prefix| line.trim().strip_prefix(prefix))?;
+    let comment = comment.trim().trim_end_matches("-->").trim();
+    let word = ["File:", "file:", "Path:", "path:"]
+        .iter()
+        .find_map(|prefix| comment.strip_prefix(prefix))
+        .unwrap_or(comment)
+        .trim();
+    (!word.contains(' ') && looks_like_path(word)).then(|| word.to_owned())
+}
+
+fn looks_like_path(word: &str) -> bool {
+    let name = word.rsplit('/').next().unwrap_or(word);
+    name.rsplit_once('.')
+        .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty())
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || "/._-".contains(c))
+}
+
+/// The file in the working tree at the path, or the only one ending with it
+fn find_path(dir: &Path, path: &str) -> Result<Option<PathBuf>> {
+    let direct = in_dir(dir, path);
+    if direct.exists() {
+        return Ok(Some(direct));
+    }
+    let suffix = format!("/{}", path.trim_start_matches("./"));
+    let found: Vec<PathBuf> = files(dir)?
+        .into_iter()
+        .filter(|file| format!("/{}", file.to_string_lossy()).ends_with(&suffix))
+        .collect();
+    match found.as_slice() {
+        [file] => Ok(Some(in_dir(dir, file))),
+        // A new file
+        [] => Ok(Some(direct)),
+        _ => bail!(
+            "Several files match {path}, choose one with --file: {}",
+            list(&found)
+        ),
+    }
+}
+
+/// The file defining the definitions of the code, with its content
+fn find_file(dir: &Path, code: &str) -> Result<(PathBuf, String)> {
+    let lines: Vec<&str> = code.lines().collect();
+    let (items, _) = items(&lines);
+    if items.is_empty() {
+        bail!("The code block has no definitions to look for, choose the file with --file");
+    }
+    // Files by the number of definitions found in them, and how similar they are
+    let mut best: Vec<(usize, usize, PathBuf, String)> = Vec::new();
+    for file in files(dir)? {
+        let path = in_dir(dir, &file);
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let target: Vec<&str> = content.lines().collect();
+        let mut found = 0;
+        let mut similarity = 0;
+        for item in &items {
+            if let Some((_, score)) = locate(&target, &lines, item) {
+                found += 1;
+                similarity += score;
+            }
+        }
+        if found > 0 {
+            best.push((found, similarity, file, content));
+        }
+    }
+    best.sort_by_key(|b| std::cmp::Reverse((b.0, b.1)));
+    match best.as_slice() {
+        [] => bail!(
+            "None of the definitions of the code block were found in {}, choose the file with \
+            --file",
+            dir.display()
+        ),
+        [first, second, ..] if (first.0, first.1) == (second.0, second.1) => {
+            let tied: Vec<PathBuf> = best
+                .iter()
+                .take_while(|b| (b.0, b.1) == (first.0, first.1))
+                .map(|b| b.2.clone())
+                .collect();
+            bail!(
+                "The code block matches several files, choose one with --file: {}",
+                list(&tied)
+            )
+        }
+        [first, ..] => Ok((in_dir(dir, &first.2), first.3.clone())),
+    }
+}
+
+/// The path of a file in the working tree, without a leading `./`
+fn in_dir(dir: &Path, file: impl AsRef<Path>) -> PathBuf {
+    if dir == Path::new(".") {
+        file.as_ref().to_owned()
+    } else {
+        dir.join(file)
+    }
+}
+
+fn list(files: &[PathBuf]) -> String {
+    let names: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+    names.join(", ")
+}
+
+/// Files of the working tree, relative to it: those tracked or not ignored by git, otherwise
+/// all files outside of hidden and build directories
+fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["ls-files", "--cached", "--others", "--exclude-standard"])
+        .output();
+    let mut files: Vec<PathBuf> = match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(PathBuf::from)
+            .collect(),
+        _ => {
+            let mut files = Vec::new();
+            walk(dir, Path::new(""), &mut files)?;
+            files
+        }
+    };
+    files.retain(|file| {
+        std::fs::metadata(in_dir(dir, file)).is_ok_and(|m| m.is_file() && m.len() <= MAX_FILE_SIZE)
+    });
+    Ok(files)
+}
+
+fn walk(root: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let dir = root.join(relative);
+    for entry in std::fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?
+    {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_str()) {
+                walk(root, &path, files)?;
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A definition in a list of lines, as indexes of its first line (including attributes and
+/// doc comments), of the line with the keyword, and of its last line
+#[derive(Debug)]
+struct Item {
+    start: usize,
+    def: usize,
+    end: usize,
+    key: String,
+}
+
+/// The top-level definitions of the lines, and the lines outside of them
+fn items(lines: &[&str]) -> (Vec<Item>, Vec<usize>) {
+    let mut items = Vec::new();
+    let mut others = Vec::new();
+    let mut prelude = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if let Some(key) = definition_key(lines[i]) {
+            let end = block_end(lines, i);
+            items.push(Item {
+                start: prelude.first().copied().unwrap_or(i),
+                def: i,
+                end,
+                key,
+            });
+            prelude.clear();
+            i = end + 1;
+            continue;
+        }
+        if is_prelude(lines[i]) {
+            prelude.push(i);
+        } else {
+            others.append(&mut prelude);
+            others.push(i);
+        }
+        i += 1;
+    }
+    others.append(&mut prelude);
+    (items, others)
+}
+
+/// Kind and name of the definition starting on the line, e.g. `fn main`
+fn definition_key(line: &str) -> Option<String> {
+    let mut rest = line.trim_start();
+    loop {
+        let word = rest.split_whitespace().next()?;
+        if MODIFIERS.contains(&word) || word.starts_with("pub(") {
+            rest = rest[word.len()..].trim_start();
+            continue;
+        }
+        let kind = DEFINITION_KINDS.iter().find(|kind| {
+            word == **kind || (word.starts_with(**kind) && word[kind.len()..].starts_with('<'))
+        })?;
+        let mut rest = rest[kind.len()..].trim_start();
+        if *kind == "impl" {
+            // The whole header, e.g. `impl Display for Message`
+            let header = rest.split(['{', '\n']).next().unwrap_or_default();
+            let header = header.split(" where").next().unwrap_or_default();
+            let header: Vec<&str> = header.split_whitespace().collect();
+            return Some(format!("impl {}", header.join(" ")));
+        }
+        if *kind == "func" && rest.starts_with('(') {
+            // The receiver of a Go method
+            rest = rest.split_once(')')?.1.trim_start();
+        }
+        let name: String = rest
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+            .collect();
+        return (!name.is_empty()).then(|| format!("{kind} {name}"));
+    }
+}
+
+/// Whether the line belongs to the following definition: an attribute, a decorator or a
+/// comment
+fn is_prelude(line: &str) -> bool {
+    let line = line.trim_start();
+    ["#", "//", "@", "/*", "* ", "*/"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+        || line == "*"
+}
+
+/// Whether the line brings a dependency into scope
+fn is_import(line: &str) -> bool {
+    ["use ", "pub use ", "import ", "from ", "#include "]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+/// Index of the last line of the definition starting on the given line: up to the matching
+/// closing brace, up to a semicolon ending a declaration, or for Python, up to the last line
+/// indented deeper
+fn block_end(lines: &[&str], def: usize) -> usize {
+    let mut braces = 0;
+    let mut parens = 0i32;
+    for (i, line) in lines.iter().enumerate().skip(def) {
+        let code = strip_strings_and_comments(line);
+        for c in code.chars() {
+            match c {
+                '{' => braces += 1,
+                '}' => {
+                    braces -= 1;
+                    if braces == 0 {
+                        return i;
+                    }
+                }
+                '(' | '[' => parens += 1,
+                ')' | ']' => parens -= 1,
+                _ => {}
+            }
+        }
+        if braces == 0 && parens <= 0 {
+            let code = code.trim_end();
+            if code.ends_with(';') {
+                return i;
+            }
+            if code.ends_with(':') {
+                return indented_end(lines, def, i);
+            }
+        }
+    }
+    lines.len() - 1
+}
+
+/// Index of the last line of a block made of the lines after `header` indented deeper than
+/// the line `def`
+fn indented_end(lines: &[&str], def: usize, header: usize) -> usize {
+    let depth = indent(lines[def]).len();
+    let mut end = header;
+    for (i, line) in lines.iter().enumerate().skip(header + 1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indent(line).len() <= depth {
+            break;
+        }
+        end = i;
+    }
+    end
+}
+
+/// The line without string literals and line comments, to count brackets
+fn strip_strings_and_comments(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                if c == '\\' {
+                    chars.next();
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '"' | '`' => quote = Some(c),
+                '/' if chars.peek() == Some(&'/') => break,
+                '#' if out.trim().is_empty() => break,
+                _ => out.push(c),
+            },
+        }
+    }
+    out
+}
+
+fn indent(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Where the definition of the code appears in the target lines, with the number of lines
+/// they have in common. Among definitions of the same name, e.g. `fn new` of several types,
+/// the most similar one.
+fn locate(target: &[&str], code: &[&str], item: &Item) -> Option<(Item, usize)> {
+    let snippet: Vec<&str> = code[item.start..=item.end]
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    let mut best: Option<(Item, usize)> = None;
+    for (i, line) in target.iter().enumerate() {
+        if definition_key(line).as_ref() != Some(&item.key) {
+            continue;
+        }
+        let end = block_end(target, i);
+        let score = target[i..=end]
+            .iter()
+            .filter(|line| snippet.contains(&line.trim()))
+            .count();
+        if best.as_ref().is_none_or(|(_, best)| score > *best) {
+            let mut start = i;
+            // Replace the attributes and comments too, if the code has them
+            if item.start < item.def {
+                while start > 0 && is_prelude(target[start - 1]) {
+                    start -= 1;
+                }
+            }
+            let found = Item {
+                start,
+                def: i,
+                end,
+                key: item.key.clone(),
+            };
+            best = Some((found, score));
+        }
+    }
+    best
+}
+
+/// Apply the code to the content of a file: its definitions replace those found in the file,
+/// and are appended otherwise, and missing imports are added. Code without definitions
+/// replaces the file, as does any code for an empty file.
+fn patch(old: &str, code: &str) -> String {
+    let target: Vec<&str> = old.lines().collect();
+    let lines: Vec<&str> = code.lines().collect();
+    let (items, others) = items(&lines);
+
+    let mut replacements = Vec::new();
+    let mut appended = Vec::new();
+    for item in &items {
+        match locate(&target, &lines, item) {
+            Some((found, _))
+                if !replacements
+                    .iter()
+                    .any(|(r, _): &(Item, _)| found.start <= r.end && r.start <= found.end) =>
+            {
+                let block = reindent(
+                    &lines[item.start..=item.end],
+                    lines[item.def],
+                    indent(target[found.def]),
+                );
+                replacements.push((found, block));
+            }
+            _ => appended.push(lines[item.start..=item.end].join("\n")),
+        }
+    }
+    if items.is_empty() || old.trim().is_empty() {
+        let mut new = code.trim_end().to_owned();
+        new.push('\n');
+        return new;
+    }
+
+    let mut new: Vec<String> = target.iter().map(|line| line.to_string()).collect();
+    replacements.sort_by_key(|(found, _)| found.start);
+    for (found, block) in replacements.into_iter().rev() {
+        new.splice(found.start..=found.end, block);
+    }
+    for block in appended {
+        if new.last().is_some_and(|line| !line.trim().is_empty()) {
+            new.push(String::new());
+        }
+        new.extend(block.lines().map(str::to_owned));
+    }
+    let imports: Vec<&str> = others
+        .iter()
+        .map(|&i| lines[i])
+        .filter(|line| is_import(line) && !target.contains(line))
+        .collect();
+    if !imports.is_empty() {
+        let at = new
+            .iter()
+            .rposition(|line| is_import(line))
+            .map_or(0, |i| i + 1);
+        new.splice(at..at, imports.iter().map(|line| line.to_string()));
+    }
+    let mut new = new.join("\n");
+    new.push('\n');
+    new
+}
+
+/// The lines moved from the indentation of the line `def` to `indent`
+fn reindent(lines: &[&str], def: &str, indent: &str) -> Vec<String> {
+    let base = self::indent(def);
+    lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                String::new()
+            } else {
+                let rest = line.strip_prefix(base).unwrap_or(line.trim_start());
+                format!("{indent}{rest}")
+            }
+        })
+        .collect()
+}
+
+fn confirm(path: &Path) -> Result<bool> {
+    let term = console::Term::stderr();
+    if !term.is_term() || !atty::is(atty::Stream::Stdin) {
+        bail!("Pass --yes to apply the patch without a terminal to confirm it on");
+    }
+    term.write_str(&format!("Apply to {}? [y/N] ", path.display()))?;
+    Ok(is_yes(&term.read_line()?))
+}
+
+/// The text in the clipboard, read with the tool of the platform
+fn read_clipboard() -> Result<String> {
+    let commands: &[&[&str]] = if cfg!(target_os = "macos") {
+        &[&["pbpaste"]]
+    } else if cfg!(windows) {
+        &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]]
+    } else {
+        &[
+            &["wl-paste", "--no-newline"],
+            &["xclip", "-selection", "clipboard", "-o"],
+            &["xsel", "--clipboard", "--output"],
+        ]
+    };
+    for command in commands {
+        match Command::new(command[0]).args(&command[1..]).output() {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            _ => continue,
+        }
+    }
+    let tools: Vec<&str> = commands.iter().map(|command| command[0]).collect();
+    bail!(
+        "Failed to read the clipboard with {}, pipe the code block into heygpt pbpatch instead",
+        tools.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_found_in_the_file_are_replaced() {
+        let old = "fn a() {\n    1\n}\n\nfn b() {\n    2\n}\n";
+        let code = "fn b() {\n    3\n}";
+        assert_eq!(
+            patch(old, code),
+            "fn a() {\n    1\n}\n\nfn b() {\n    3\n}\n"
+        );
+    }
+
+    #[test]
+    fn new_definitions_are_appended() {
+        let old: String = (0..50)
+            .map(|i| format!("const C{i}: u32 = {i};\n"))
+            .collect();
+        // A new function as long as half of the file doesn't replace it
+        let body: String = (0..30).map(|i| format!("    let x{i} = {i};\n")).collect();
+        let code = format!("fn new() {{\n{body}}}");
+        let new = patch(&old, &code);
+        assert!(new.starts_with(&old));
+        assert!(new.ends_with(&format!("\n{code}\n")));
+    }
+
+    #[test]
+    fn code_without_definitions_or_for_an_empty_file_replaces_it() {
+        assert_eq!(patch("fn a() {}\n", "print(1)"), "print(1)\n");
+        assert_eq!(patch("", "fn a() {}"), "fn a() {}\n");
+    }
+
+    #[test]
+    fn missing_imports_are_added_after_the_others() {
+        let old = "use std::fs;\n\nfn a() {\n    fs::remove_file(\"x\");\n}\n";
+        let code = "use std::fs;\nuse std::io;\n\nfn a() {\n    io::stdin();\n}";
+        assert_eq!(
+            patch(old, code),
+            "use std::fs;\nuse std::io;\n\nfn a() {\n    io::stdin();\n}\n"
+        );
+    }
+
+    #[test]
+    fn python_methods_are_reindented() {
+        let old =
+            "class A:\n    def f(self):\n        return 1\n\n    def g(self):\n        return 2\n";
+        // Pasted without the class, at the top level
+        let code = "def f(self):\n    return 3";
+        assert_eq!(
+            patch(old, code),
+            "class A:\n    def f(self):\n        return 3\n\n    def g(self):\n        return 2\n"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use clap::Parser;
+use console::style;
+
+use crate::offline;
+use crate::pricing::{self, PRICING_URL};
+use crate::Session;
+
+/// Update and show the prices used to estimate the cost of responses with `--show-cost`
+#[derive(Parser, Debug)]
+#[command(disable_help_subcommand = true)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Action {
+    /// Download the latest pricing table
+    Update {
+        /// URL of the pricing table (default: pricing_url from the config file, or the table
+        /// maintained in the heygpt repository)
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Show the prices in USD per million tokens, with the overrides from the config file
+    Show,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    match args.action {
+        Action::Update { url } => {
+            let url = url
+                .or_else(|| session.options.pricing_url.clone())
+                .unwrap_or_else(|| PRICING_URL.to_owned());
+            offline::check_url(&session.options, "pricing table", &url)?;
+            let table = pricing::update(&url).await?;
+            println!(
+                "Updated the prices of {} models in {}",
+                table.models.len(),
+                pricing::table_path().display()
+            );
+        }
+        Action::Show => {
+            let overrides = &session.options.pricing;
+            let models = pricing::table().models.keys().chain(
+                overrides
+                    .keys()
+                    .filter(|m| !pricing::table().models.contains_key(*m)),
+            );
+            println!(
+                "{}",
+                style(format!("{:<24} {:>8} {:>8}", "Model", "Input", "Output")).bold()
+            );
+            for model in models {
+                let Some(price) = pricing::price(overrides, model) else {
+                    continue;
+                };
+                let overridden = if overrides.contains_key(model) {
+                    style(" (config)").dim().to_string()
+                } else {
+                    String::new()
+                };
+                println!(
+                    "{model:<24} {:>8} {:>8}{overridden}",
+                    price.input, price.output
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use crate::Session;
+
+const SYSTEM_PROMPT: &str = "\
+You are an expert in prompt engineering for large language models. Review the given prompt \
+template for:
+- ambiguous or contradictory instructions,
+- missing context the model would need,
+- missing instructions about the output format (structure, length, language, tone),
+- excessive length or redundancy, which costs tokens and dilutes the important instructions.
+List the issues ordered by importance, each with a concrete fix. Then propose an improved \
+version of the template in a code block. Keep all placeholders of the template unchanged.";
+
+/// Templates longer than this are likely to carry redundancy
+const LONG_PROMPT_TOKENS: usize = 1500;
+
+/// Words suggesting that the template specifies the output format
+const FORMAT_HINTS: &[&str] = &[
+    "format", "json", "markdown", "list", "table", "bullet", "respond", "output", "return",
+];
+
+/// Analyze a prompt template and propose an improved version
+#[derive(Parser, Debug)]
+#[command(trailing_var_arg = true)]
+pub struct Args {
+    /// The prompt template to analyze
+    file: PathBuf,
+
+    /// Additional context, e.g. what the prompt is used for
+    context: Vec<String>,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let template = std::fs::read_to_string(&args.file)
+        .with_context(|| format!("Failed to read {}", args.file.display()))?;
+
+    let mut prompt = format!(
+        "The prompt template:\n\n```\n{}\n```\n",
+        template.trim_end()
+    );
+    prompt.push_str(&static_findings(&template)?);
+    if !args.context.is_empty() {
+        write!(prompt, "\nContext: {}\n", args.context.join(" "))?;
+    }
+
+    let system = super::system_prompt(
+        session,
+        &super::builtin_prompt(session, "prompt-lint", SYSTEM_PROMPT),
+    );
+    session.ask(Some(system), prompt).await?;
+    Ok(())
+}
+
+/// Findings which don't need a model, to ground the review
+fn static_findings(template: &str) -> Result<String> {
+    let mut findings = String::from("\nFacts about the template:\n");
+
+    // A rough estimate, good enough to flag overly long prompts
+    let tokens = template.chars().count() / 4;
+    write!(findings, "- Length: about {tokens} tokens")?;
+    if tokens > LONG_PROMPT_TOKENS {
+        write!(findings, " (long, look for redundancy)")?;
+    }
+    findings.push('\n');
+
+    let placeholders = placeholders(template);
+    if placeholders.is_empty() {
+        writeln!(findings, "- Placeholders: none")?;
+    } else {
+        writeln!(findings, "- Placeholders: {}", placeholders.join(", "))?;
+    }
+
+    let lower = template.to_lowercase();
+    if !FORMAT_HINTS.iter().any(|hint| lower.contains(hint)) {
+        writeln!(findings, "- No mention of the output format")?;
+    }
+    Ok(findings)
+}
+
+/// Placeholders like `{{name}}`, `{name}` or `$NAME`, in order of appearance
+fn placeholders(template: &str) -> Vec<String> {
+    let is_name = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+    };
+    let mut result: Vec<String> = Vec::new();
+    let mut push = |p: String| {
+        if !result.contains(&p) {
+            result.push(p);
+        }
+    };
+
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let double = rest[start..].starts_with("{{");
+        let open = if double { 2 } else { 1 };
+        let Some(len) = rest[start + open..].find('}') else {
+            break;
+        };
+        let name = rest[start + open..start + open + len].trim();
+        if is_name(name) {
+            let close = if double { "}}" } else { "}" };
+            push(format!("{}{name}{close}", &rest[start..start + open]));
+        }
+        rest = &rest[start + open + len..];
+    }
+
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        let name: String = rest[start + 1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+        if name.chars().next().is_some_and(|c| c.is_ascii_uppercase()) {
+            push(format!("${name}"));
+        }
+        rest = &rest[start + 1..];
+    }
+    result
+}
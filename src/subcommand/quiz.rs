@@ -0,0 +1,189 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use console::style;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use serde::{Deserialize, Serialize};
+
+use crate::model::Message;
+use crate::storage::{atomic_write, data_dir};
+use crate::Session;
+
+const SYSTEM_PROMPT: &str = "\
+You are a quiz master testing the knowledge of the user on a topic. Ask one question at a time, \
+answerable in a few sentences or a short code snippet, and vary the difficulty and the aspects \
+of the topic. Never repeat a question. When grading an answer, be strict about facts but \
+tolerant of wording, and explain briefly what was right or wrong.";
+
+/// Previously missed questions which are asked again at most
+const MAX_REPEATED: usize = 5;
+
+/// Run a quiz on a topic, grading the answers and keeping score
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The topic of the quiz, e.g. "Rust lifetimes"
+    #[arg(long)]
+    topic: String,
+
+    /// Number of questions
+    #[arg(long, default_value_t = 10)]
+    n: usize,
+}
+
+#[derive(Deserialize, Debug)]
+struct Question {
+    question: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Grade {
+    correct: bool,
+    feedback: String,
+}
+
+/// Result of a question, stored for spaced repetition
+#[derive(Serialize, Deserialize, Debug)]
+struct QuizResult {
+    topic: String,
+    question: String,
+    answer: String,
+    correct: bool,
+    /// Seconds since UNIX epoch
+    timestamp: u64,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    if !session.is_stdin || !session.is_stdout {
+        bail!("quiz requires an interactive terminal");
+    }
+
+    let mut results = load_results()?;
+    let missed = missed_questions(&results, &args.topic);
+    let save = session.options.privacy.allows_logs();
+    if !save {
+        println!(
+            "{}",
+            style("The results aren't saved under this privacy level").dim()
+        );
+    }
+
+    let mut system = format!(
+        "{}\n\nThe topic is: {}",
+        super::builtin_prompt(session, "quiz", SYSTEM_PROMPT),
+        args.topic
+    );
+    if !missed.is_empty() {
+        system.push_str(
+            "\n\nThe user answered these questions incorrectly in earlier quizzes. \
+            Ask them again among the new ones, possibly rephrased:",
+        );
+        for q in &missed {
+            system.push_str(&format!("\n- {q}"));
+        }
+    }
+    session.messages.push(Message {
+        role: "system".to_string(),
+        content: super::system_prompt(session, &system),
+        meta: None,
+    });
+
+    let mut rl = DefaultEditor::new()?;
+    let mut score = 0;
+    let mut asked = 0;
+    for i in 1..=args.n {
+        session.messages.push(Message {
+            role: "user".to_string(),
+            content: format!(
+                "Ask question {i} of {}. Respond with a JSON object: {{\"question\": string}}",
+                args.n
+            ),
+            meta: None,
+        });
+        let (message, question) = session.complete_json::<Question>().await?;
+        session.messages.push(message);
+
+        println!(
+            "{} {}",
+            style(format!("Question {i}/{}:", args.n)).bold().cyan(),
+            question.question
+        );
+        let answer = match rl.readline("answer => ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(err) => bail!("Readline error: {:?}", err),
+        };
+
+        session.messages.push(Message {
+            role: "user".to_string(),
+            content: format!(
+                "My answer: {answer}\n\nGrade my answer. Respond with a JSON object: \
+                {{\"correct\": boolean, \"feedback\": string}}"
+            ),
+            meta: None,
+        });
+        let (message, grade) = session.complete_json::<Grade>().await?;
+        session.messages.push(message);
+
+        asked += 1;
+        if grade.correct {
+            score += 1;
+            println!("{} {}", style("Correct!").bold().green(), grade.feedback);
+        } else {
+            println!("{} {}", style("Incorrect.").bold().red(), grade.feedback);
+        }
+        println!();
+
+        results.push(QuizResult {
+            topic: args.topic.clone(),
+            question: question.question,
+            answer,
+            correct: grade.correct,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        });
+        // Saved as graded, so that an error or an interrupted quiz keeps the answers so far
+        if save {
+            atomic_write(&results_file(), serde_json::to_string_pretty(&results)?)?;
+        }
+    }
+
+    if asked > 0 {
+        println!("{}", style(format!("Score: {score}/{asked}")).bold());
+    }
+    Ok(())
+}
+
+fn results_file() -> PathBuf {
+    data_dir().join("quiz.json")
+}
+
+fn load_results() -> Result<Vec<QuizResult>> {
+    match std::fs::read_to_string(results_file()) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Questions of the topic whose latest answer was incorrect, most recent first
+fn missed_questions<'a>(results: &'a [QuizResult], topic: &str) -> Vec<&'a str> {
+    let mut missed: Vec<&str> = Vec::new();
+    let mut seen: Vec<&str> = Vec::new();
+    for r in results
+        .iter()
+        .rev()
+        .filter(|r| r.topic.eq_ignore_ascii_case(topic))
+    {
+        if seen.contains(&r.question.as_str()) {
+            continue;
+        }
+        seen.push(&r.question);
+        if !r.correct {
+            missed.push(&r.question);
+        }
+    }
+    missed.truncate(MAX_REPEATED);
+    missed
+}
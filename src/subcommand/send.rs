@@ -0,0 +1,30 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::control;
+use crate::Session;
+
+/// Send a message to an interactive session started with `--listen NAME`, e.g. from another
+/// terminal or a script. The message is added to the next prompt of the session.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Name of the session, as given to `--listen`
+    #[arg(long)]
+    session: String,
+
+    /// The message (default: read from stdin)
+    #[arg(trailing_var_arg = true)]
+    message: Vec<String>,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let message = if args.message.is_empty() {
+        session.read_stdin()?.unwrap_or_default()
+    } else {
+        args.message.join(" ")
+    };
+    if message.trim().is_empty() {
+        bail!("The message is empty");
+    }
+    control::send(&args.session, &message)
+}
@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use console::style;
+
+use crate::model::Message;
+use crate::storage::atomic_write;
+use crate::Session;
+
+/// Run a prompt with every combination of parameter values, and compare the outputs and
+/// metrics, e.g. `heygpt sweep --param temperature=0,0.5,1.0 --runs 3 write a slogan`
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Values of a parameter, e.g. `temperature=0,0.5,1.0`. Parameters are `model`,
+    /// `temperature` and `top_p`. May be repeated.
+    #[arg(long = "param", value_name = "NAME=VALUES", required = true)]
+    params: Vec<String>,
+
+    /// Runs of each combination
+    #[arg(long, default_value_t = 1)]
+    runs: usize,
+
+    /// Write a Markdown report with all outputs to this file
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// The prompt to run
+    #[arg(required = true, trailing_var_arg = true)]
+    prompt: Vec<String>,
+}
+
+/// Outputs and metrics of one combination of parameter values
+struct Row {
+    values: Vec<(String, String)>,
+    outputs: Vec<String>,
+    latency_ms: Vec<u128>,
+    completion_tokens: Vec<isize>,
+}
+
+impl Row {
+    fn label(&self) -> String {
+        self.values
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn mean_latency_ms(&self) -> u128 {
+        self.latency_ms.iter().sum::<u128>() / self.latency_ms.len().max(1) as u128
+    }
+
+    /// Mean completion tokens, if the API reported them
+    fn mean_tokens(&self) -> Option<isize> {
+        if self.completion_tokens.is_empty() {
+            return None;
+        }
+        Some(self.completion_tokens.iter().sum::<isize>() / self.completion_tokens.len() as isize)
+    }
+
+    fn mean_chars(&self) -> usize {
+        self.outputs
+            .iter()
+            .map(|o| o.chars().count())
+            .sum::<usize>()
+            / self.outputs.len().max(1)
+    }
+
+    /// Number of different outputs among the runs
+    fn distinct(&self) -> usize {
+        self.outputs.iter().collect::<HashSet<_>>().len()
+    }
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    if args.runs == 0 {
+        bail!("--runs must be at least 1");
+    }
+    let mut params = Vec::new();
+    for param in &args.params {
+        let Some((name, values)) = param.split_once('=') else {
+            bail!("Invalid --param {param}, expected NAME=VALUES, e.g. temperature=0,0.5,1.0");
+        };
+        let values: Vec<String> = values.split(',').map(|v| v.trim().to_owned()).collect();
+        // Validate the values before running anything
+        for value in &values {
+            session
+                .apply_param(name, value)
+                .with_context(|| format!("Invalid --param {param}"))?;
+        }
+        params.push((name.to_owned(), values));
+    }
+    let prompt = args.prompt.join(" ");
+    let system = session.system_prompt(session.options.system.clone());
+
+    let mut rows = Vec::new();
+    for values in combinations(&params) {
+        let mut row = Row {
+            values,
+            outputs: Vec::new(),
+            latency_ms: Vec::new(),
+            completion_tokens: Vec::new(),
+        };
+        for (name, value) in &row.values {
+            session.apply_param(name, value)?;
+        }
+        for _ in 0..args.runs {
+            session.messages.clear();
+            if let Some(system) = &system {
+                session.messages.push(Message {
+                    role: "system".to_string(),
+                    content: system.clone(),
+                    meta: None,
+                });
+            }
+            session.messages.push(Message {
+                role: "user".to_string(),
+                content: prompt.clone(),
+                meta: None,
+            });
+            let start = Instant::now();
+            let response = session.complete().await?;
+            row.latency_ms.push(start.elapsed().as_millis());
+            if let Some(usage) = response.meta.and_then(|m| m.usage) {
+                row.completion_tokens.push(usage.completion_tokens);
+            }
+            row.outputs.push(response.content.trim().to_owned());
+        }
+        println!(
+            "{}  {} ms, {} tokens, {} chars, {}/{} distinct",
+            style(row.label()).bold(),
+            row.mean_latency_ms(),
+            row.mean_tokens()
+                .map_or_else(|| "?".to_owned(), |t| t.to_string()),
+            row.mean_chars(),
+            row.distinct(),
+            args.runs
+        );
+        rows.push(row);
+    }
+
+    if let Some(path) = &args.report {
+        atomic_write(path, report(&prompt, &rows)?)?;
+        eprintln!(
+            "{}",
+            style(format!("Wrote the report to {}", path.display()))
+                .bold()
+                .green()
+        );
+    }
+    Ok(())
+}
+
+/// All combinations of the parameter values, the last parameter varying fastest
+fn combinations(params: &[(String, Vec<String>)]) -> Vec<Vec<(String, String)>> {
+    params
+        .iter()
+        .fold(vec![Vec::new()], |combinations, (name, values)| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((name.clone(), value.clone()));
+                        combination
+                    })
+                })
+                .collect()
+        })
+}
+
+fn report(prompt: &str, rows: &[Row]) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "# Parameter sweep\n")?;
+    writeln!(out, "Prompt: {prompt}\n")?;
+    writeln!(
+        out,
+        "| Parameters | Latency (ms) | Completion tokens | Characters | Distinct outputs |"
+    )?;
+    writeln!(out, "|---|---|---|---|---|")?;
+    for row in rows {
+        writeln!(
+            out,
+            "| {} | {} | {} | {} | {}/{} |",
+            row.label(),
+            row.mean_latency_ms(),
+            row.mean_tokens()
+                .map_or_else(|| "?".to_owned(), |t| t.to_string()),
+            row.mean_chars(),
+            row.distinct(),
+            row.outputs.len()
+        )?;
+    }
+    for row in rows {
+        writeln!(out, "\n## {}", row.label())?;
+        for (i, output) in row.outputs.iter().enumerate() {
+            writeln!(out, "\n### Run {}\n\n{output}", i + 1)?;
+        }
+    }
+    Ok(out)
+}
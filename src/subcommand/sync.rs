@@ -0,0 +1,119 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use console::style;
+
+use crate::offline;
+use crate::storage::data_dir;
+use crate::Session;
+
+/// Branch holding the synchronized data in the remote repository
+const BRANCH: &str = "heygpt";
+
+/// Synchronize saved sessions and other data with a git remote, for use on multiple machines.
+/// Local changes are committed, merged with the remote ones and pushed.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// URL of the git remote (default: sync_remote from the config file, or the remote of the
+    /// previous sync)
+    #[arg(long)]
+    remote: Option<String>,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let dir = data_dir();
+    std::fs::create_dir_all(&dir)?;
+    let initialized = dir.join(".git").exists();
+
+    let remote = match args.remote.or_else(|| session.options.sync_remote.clone()) {
+        Some(remote) if initialized => {
+            git(&dir, &["remote", "set-url", "origin", &remote])?;
+            remote
+        }
+        Some(remote) => {
+            git(&dir, &["init", "-q", "-b", BRANCH])?;
+            git(&dir, &["remote", "add", "origin", &remote])?;
+            remote
+        }
+        None if initialized => git(&dir, &["remote", "get-url", "origin"])?,
+        None => bail!("Set the git remote with --remote or sync_remote in ~/.heygpt.toml"),
+    };
+    offline::check_git_remote(&session.options, &remote)?;
+
+    git(&dir, &["add", "-A"])?;
+    if !git(&dir, &["status", "--porcelain"])?.is_empty() {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown host".to_owned());
+        git(
+            &dir,
+            &[
+                "-c",
+                "user.name=heygpt",
+                "-c",
+                "user.email=heygpt@localhost",
+                "commit",
+                "-q",
+                "-m",
+                &format!("Sync from {host}"),
+            ],
+        )?;
+    }
+
+    git(&dir, &["fetch", "-q", "origin"])?;
+    let remote_branch = format!("origin/{BRANCH}");
+    if git(&dir, &["rev-parse", "-q", "--verify", &remote_branch]).is_ok() {
+        let merged = git(
+            &dir,
+            &[
+                "-c",
+                "user.name=heygpt",
+                "-c",
+                "user.email=heygpt@localhost",
+                "merge",
+                "-q",
+                "--no-edit",
+                "--allow-unrelated-histories",
+                &remote_branch,
+            ],
+        );
+        if let Err(err) = merged {
+            let conflicts = git(&dir, &["diff", "--name-only", "--diff-filter=U"])?;
+            if conflicts.is_empty() {
+                return Err(err);
+            }
+            git(&dir, &["merge", "--abort"])?;
+            bail!(
+                "Changed both here and on the remote, resolve in {}:\n{conflicts}",
+                dir.display()
+            );
+        }
+    }
+
+    git(&dir, &["push", "-q", "origin", &format!("HEAD:{BRANCH}")])?;
+    eprintln!(
+        "{}",
+        style(format!("Synchronized with {remote}")).bold().green()
+    );
+    Ok(())
+}
+
+/// Run git in the directory, returning its output
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.iter()
+                .find(|a| !a.starts_with('-') && !a.contains('='))
+                .unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
@@ -0,0 +1,269 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use console::style;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::attachment::Attachment;
+use crate::i18n::tr;
+use crate::Session;
+
+const SAMPLE_FILE: &str = "\
+Meeting notes, 3 March
+- Release 1.2 slips by a week, the migration tests are flaky
+- Alice takes over the changelog
+- Next meeting: Thursday";
+
+/// A guided walkthrough of heygpt against a built-in mock provider, which costs no API credits
+#[derive(Parser, Debug)]
+pub struct Args {}
+
+pub async fn run(session: &mut Session, _args: Args) -> Result<()> {
+    if !session.is_stdin || !session.is_stdout {
+        bail!("tour requires an interactive terminal");
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    session.options.api_base_url = format!("http://{}", listener.local_addr()?);
+    session.options.api_key = "tour".to_owned();
+    session.options.save_sessions = false;
+    tokio::spawn(serve(listener));
+
+    let mut rl = DefaultEditor::new()?;
+
+    step(
+        "Welcome to heygpt",
+        "This tour shows the main features of heygpt. The answers come from a mock provider \
+        built into heygpt, so nothing is sent to an API and no credits are used.",
+    );
+    if !next(&mut rl)? {
+        return Ok(());
+    }
+
+    step(
+        "Asking a question",
+        "Put the question on the command line for a one-shot answer, printed as it's generated. \
+        Without a question, heygpt starts a conversation.",
+    );
+    command("heygpt how do I undo the last git commit?");
+    session
+        .ask(None, "how do I undo the last git commit?".to_owned())
+        .await?;
+    if !next(&mut rl)? {
+        return Ok(());
+    }
+
+    step(
+        "Attaching files",
+        "`--file` attaches a file or URL to the question. Text files are attached as is, PDFs \
+        are converted to text. In scripts, `heygpt -q` attaches the output piped into it, e.g. \
+        `make 2>&1 | heygpt -q why does the build fail?`.",
+    );
+    let path = std::env::temp_dir().join("heygpt-tour-notes.txt");
+    std::fs::write(&path, SAMPLE_FILE)?;
+    command(&format!(
+        "heygpt --file {} what was decided?",
+        path.display()
+    ));
+    session.messages.clear();
+    session.attachments = vec![Attachment::load(&path.to_string_lossy()).await?];
+    let _ = std::fs::remove_file(&path);
+    session.ask(None, "what was decided?".to_owned()).await?;
+    if !next(&mut rl)? {
+        return Ok(());
+    }
+
+    step(
+        "Prompts and answer length",
+        "`--system=\"...\"` sets a system prompt, and `--brief` or `--detailed` the length of \
+        answers. The built-in prompts of subcommands such as `heygpt ops` can be replaced in \
+        the `[prompts]` table of ~/.heygpt.toml, and `heygpt prompt-lint` reviews your own.",
+    );
+    command("heygpt --brief --system=\"You are a Rust expert\" what is a lifetime?");
+    session.messages.clear();
+    session.options.brief = true;
+    session
+        .ask(
+            Some("You are a Rust expert".to_owned()),
+            "what is a lifetime?".to_owned(),
+        )
+        .await?;
+    session.options.brief = false;
+    if !next(&mut rl)? {
+        return Ok(());
+    }
+
+    step(
+        "Conversations",
+        "Commands starting with a backslash control the conversation:",
+    );
+    for (cmd, description) in tr().help_lines {
+        println!("  {cmd:<13} {description}");
+    }
+    println!(
+        "\nTry it now: ask something, then enter `\\h -v`, `\\checkpoint start` or `\\params`. \
+        Press Ctrl-D to end the conversation."
+    );
+    session.messages.clear();
+    session.run_interactive().await?;
+
+    step(
+        "Next steps",
+        "Run `heygpt doctor` to check your API key and model, and `heygpt --help` for all \
+        options and subcommands. Outside this tour, conversations are saved and listed with \
+        `heygpt history list`.",
+    );
+    Ok(())
+}
+
+fn step(title: &str, text: &str) {
+    println!("\n{}\n{text}", style(title).bold().cyan());
+}
+
+fn command(command: &str) {
+    println!("\n{} {}", style("$").dim(), style(command).bold());
+}
+
+/// Wait for Enter, returning `false` if the user quits
+fn next(rl: &mut DefaultEditor) -> Result<bool> {
+    match rl.readline(&format!(
+        "\n{}",
+        style("Press Enter to continue, q to quit ").dim()
+    )) {
+        Ok(line) => Ok(line.trim() != "q"),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => Ok(false),
+        Err(err) => bail!("Readline error: {:?}", err),
+    }
+}
+
+/// The mock provider, answering chat completions with canned responses
+async fn serve(listener: TcpListener) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream).await {
+                log::debug!("Mock provider failed: {err:#}");
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream) -> Result<()> {
+    // Read the headers, then the body of the given length
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    let body_start = loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            bail!("Incomplete request");
+        }
+        request.extend_from_slice(&buf[..n]);
+        if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+    let headers = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+    let length = headers
+        .lines()
+        .find_map(|l| l.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0);
+    while request.len() < body_start + length {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            bail!("Incomplete request");
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let body: Value = serde_json::from_slice(&request[body_start..])?;
+
+    let question = body["messages"]
+        .as_array()
+        .and_then(|m| m.last())
+        .and_then(|m| m["content"].as_str())
+        .unwrap_or_default();
+    let answer = answer(question);
+    let model = body["model"].as_str().unwrap_or("mock");
+    let usage = json!({"prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0});
+
+    if body["stream"].as_bool() == Some(true) {
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n",
+            )
+            .await?;
+        let chunk = |delta: Value| {
+            json!({
+                "id": "tour", "object": "chat.completion.chunk", "created": 0, "model": model,
+                "choices": [{"index": 0, "delta": delta, "finish_reason": null}],
+            })
+        };
+        stream
+            .write_all(format!("data: {}\n\n", chunk(json!({"role": "assistant"}))).as_bytes())
+            .await?;
+        for word in answer.split_inclusive(' ') {
+            let event = format!("data: {}\n\n", chunk(json!({"content": word})));
+            stream.write_all(event.as_bytes()).await?;
+            tokio::time::sleep(Duration::from_millis(30)).await;
+        }
+        if body["stream_options"]["include_usage"].as_bool() == Some(true) {
+            let event = json!({
+                "id": "tour", "object": "chat.completion.chunk", "created": 0, "model": model,
+                "choices": [], "usage": usage,
+            });
+            stream
+                .write_all(format!("data: {event}\n\n").as_bytes())
+                .await?;
+        }
+        stream.write_all(b"data: [DONE]\n\n").await?;
+    } else {
+        let response = json!({
+            "id": "tour", "object": "chat.completion", "created": 0, "model": model,
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": answer},
+                "finish_reason": "stop",
+            }],
+            "usage": usage,
+        })
+        .to_string();
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .as_bytes(),
+            )
+            .await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Canned answers for the questions of the tour
+fn answer(question: &str) -> String {
+    if question.contains("undo the last git commit") {
+        "Use `git reset --soft HEAD~1` to undo the commit but keep its changes staged, or \
+        `git reset --hard HEAD~1` to discard them as well."
+            .to_owned()
+    } else if question.contains("Meeting notes") {
+        "Release 1.2 is postponed by a week because of flaky migration tests, and Alice takes \
+        over the changelog. The next meeting is on Thursday."
+            .to_owned()
+    } else if question.contains("what is a lifetime") {
+        "A lifetime is the scope for which a reference is valid. The compiler checks that no \
+        reference outlives the data it points to."
+            .to_owned()
+    } else {
+        let line = question.lines().next().unwrap_or_default();
+        format!(
+            "This answer comes from the mock provider of the tour. With a real model, you'd get \
+            an answer to \"{line}\" here."
+        )
+    }
+}
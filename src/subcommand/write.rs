@@ -0,0 +1,255 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use crate::model::Message;
+use crate::storage::atomic_write;
+use crate::Session;
+
+const SYSTEM_PROMPT: &str = "\
+You are a skilled writer working on a long document one section at a time, following an outline. \
+Write only the requested section, in Markdown, starting with its heading. Keep the style, tone \
+and terminology consistent with the previous sections and don't repeat their content.";
+
+/// Characters from the end of the draft given as context for the next section
+const PREVIOUS_CONTEXT_LEN: usize = 4000;
+
+/// Write a long document section by section following an outline.
+/// Progress is saved after each section, so an interrupted run can be resumed with --continue.
+#[derive(Parser, Debug)]
+#[command(trailing_var_arg = true)]
+pub struct Args {
+    /// Outline in Markdown. Each top-level heading, or otherwise each top-level list item,
+    /// becomes a section
+    #[arg(long)]
+    outline: PathBuf,
+
+    /// File to write the draft to
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Resume an interrupted run
+    #[arg(long = "continue")]
+    resume: bool,
+
+    /// Additional instructions, e.g. about style or audience
+    instructions: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Section {
+    title: String,
+    notes: String,
+}
+
+/// Progress of a run, saved next to the draft until it's finished
+#[derive(Serialize, Deserialize, Debug)]
+struct Progress {
+    /// Titles of all sections, to detect changes of the outline
+    sections: Vec<String>,
+    /// Additional instructions, reused when resuming
+    instructions: String,
+    /// Number of completed sections
+    completed: usize,
+    /// Length of the draft in bytes after the last completed section
+    offset: u64,
+}
+
+pub async fn run(session: &mut Session, args: Args) -> Result<()> {
+    let outline = std::fs::read_to_string(&args.outline)
+        .with_context(|| format!("Failed to read {}", args.outline.display()))?;
+    let sections = parse_outline(&outline);
+    if sections.is_empty() {
+        bail!("No sections found in the outline");
+    }
+    let titles: Vec<String> = sections.iter().map(|s| s.title.clone()).collect();
+
+    let system = super::builtin_prompt(session, "write", SYSTEM_PROMPT);
+    let progress_file = progress_file(&args.output);
+    let mut progress = if args.resume {
+        let content = std::fs::read_to_string(&progress_file).with_context(|| {
+            format!(
+                "No interrupted run to continue for {}",
+                args.output.display()
+            )
+        })?;
+        let mut progress: Progress = serde_json::from_str(&content)?;
+        if progress.sections != titles {
+            bail!("The outline has changed since the interrupted run");
+        }
+        if !args.instructions.is_empty() {
+            progress.instructions = args.instructions.join(" ");
+        }
+        progress
+    } else {
+        if args.output.exists() {
+            bail!(
+                "{} already exists. Use --continue to resume an interrupted run",
+                args.output.display()
+            );
+        }
+        Progress {
+            sections: titles,
+            instructions: args.instructions.join(" "),
+            completed: 0,
+            offset: 0,
+        }
+    };
+    // Before the output exists, so that an interruption in the first section can be resumed
+    atomic_write(&progress_file, serde_json::to_string(&progress)?)?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&args.output)
+        .with_context(|| format!("Failed to open {}", args.output.display()))?;
+    // Drop the partial section written before the interruption
+    file.set_len(progress.offset)?;
+    file.seek(SeekFrom::End(0))?;
+
+    for (i, section) in sections.iter().enumerate().skip(progress.completed) {
+        eprintln!(
+            "{}",
+            style(format!(
+                "Writing section {}/{}: {}",
+                i + 1,
+                sections.len(),
+                section.title
+            ))
+            .bold()
+            .cyan()
+        );
+
+        let mut prompt = format!("The outline of the document:\n\n{}\n", outline.trim());
+        if !progress.instructions.is_empty() {
+            prompt.push_str(&format!("\nInstructions: {}\n", progress.instructions));
+        }
+        let previous = previous_context(&mut file, progress.offset)?;
+        if !previous.is_empty() {
+            prompt.push_str(&format!(
+                "\nThe end of the previous section:\n\n{previous}\n"
+            ));
+        }
+        prompt.push_str(&format!(
+            "\nNow write section {} of {}: {}",
+            i + 1,
+            sections.len(),
+            section.title
+        ));
+        if !section.notes.is_empty() {
+            prompt.push_str(&format!("\nNotes for this section:\n{}", section.notes));
+        }
+
+        session.messages.clear();
+        session.messages.push(Message {
+            role: "system".to_string(),
+            content: super::system_prompt(session, &system),
+            meta: None,
+        });
+        session.messages.push(Message {
+            role: "user".to_string(),
+            content: prompt,
+            meta: None,
+        });
+
+        session.output_file = Some(file.try_clone()?);
+        if i > 0 {
+            session.print_content("\n")?;
+        }
+        session.complete_and_print().await?;
+        session.output_file = None;
+
+        file.sync_data()?;
+        progress.completed = i + 1;
+        progress.offset = file.stream_position()?;
+        atomic_write(&progress_file, serde_json::to_string(&progress)?)?;
+    }
+
+    std::fs::remove_file(&progress_file)?;
+    eprintln!(
+        "{}",
+        style(format!("Draft written to {}", args.output.display()))
+            .bold()
+            .green()
+    );
+    Ok(())
+}
+
+fn progress_file(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".progress.json");
+    PathBuf::from(path)
+}
+
+/// Split a Markdown outline into sections by its top-level headings or, if there are none,
+/// by its top-level list items. Further lines become the notes of the section.
+fn parse_outline(outline: &str) -> Vec<Section> {
+    // The top level is the highest one with multiple headings, skipping a document title
+    let mut levels: Vec<usize> = outline
+        .lines()
+        .filter(|l| l.starts_with('#'))
+        .map(|l| l.chars().take_while(|&c| c == '#').count())
+        .collect();
+    levels.sort();
+    let heading_level = levels
+        .windows(2)
+        .find(|w| w[0] == w[1])
+        .map(|w| w[0])
+        .or(levels.first().copied());
+
+    let section_title = |line: &str| -> Option<String> {
+        match heading_level {
+            Some(level) => {
+                let hashes = line.chars().take_while(|&c| c == '#').count();
+                (hashes == level).then(|| line[level..].trim().to_owned())
+            }
+            None => {
+                let item = line
+                    .strip_prefix("- ")
+                    .or_else(|| line.strip_prefix("* "))
+                    .or_else(|| {
+                        let digits = line.chars().take_while(char::is_ascii_digit).count();
+                        line[digits..].strip_prefix(". ").filter(|_| digits > 0)
+                    })?;
+                Some(item.trim().to_owned())
+            }
+        }
+    };
+
+    let mut sections: Vec<Section> = Vec::new();
+    for line in outline.lines() {
+        if let Some(title) = section_title(line) {
+            sections.push(Section {
+                title,
+                notes: String::new(),
+            });
+        } else if let Some(section) = sections.last_mut() {
+            if !line.trim().is_empty() {
+                section.notes.push_str(line.trim_end());
+                section.notes.push('\n');
+            }
+        }
+    }
+    for section in &mut sections {
+        section.notes = section.notes.trim_end().to_owned();
+    }
+    sections
+}
+
+/// Read the end of the draft before `offset`
+fn previous_context(file: &mut std::fs::File, offset: u64) -> Result<String> {
+    let start = offset.saturating_sub(PREVIOUS_CONTEXT_LEN as u64);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.by_ref().take(offset - start).read_to_end(&mut buf)?;
+    file.seek(SeekFrom::Start(offset))?;
+    // The start may be in the middle of a multi-byte character
+    Ok(String::from_utf8_lossy(&buf).trim().to_owned())
+}
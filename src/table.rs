@@ -0,0 +1,280 @@
+use console::{measure_text_width, pad_str, Alignment};
+use serde_json::Value;
+
+/// Renders Markdown tables in streamed text as aligned terminal tables.
+///
+/// Text is passed through as it arrives, except lines starting with `|`, which are held back
+/// until the table ends. Tables in code blocks are left alone.
+#[derive(Default)]
+pub struct TableBuffer {
+    /// The current line, up to the last chunk
+    line: String,
+    /// Whether the first visible character of the current line was seen
+    decided: bool,
+    /// Whether the current line is held back as a table row
+    held: bool,
+    /// Rows of the current table
+    rows: Vec<String>,
+    in_code_block: bool,
+}
+
+impl TableBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk of text, returning the text to print now
+    pub fn push(&mut self, chunk: &str) -> String {
+        let mut out = String::new();
+        for part in chunk.split_inclusive('\n') {
+            let complete = part.ends_with('\n');
+            self.line.push_str(part);
+            if !self.decided {
+                // Leading whitespace is held until the line turns out to be a row or not
+                if self.line.trim().is_empty() && !complete {
+                    continue;
+                }
+                self.decided = true;
+                self.held = !self.in_code_block && self.line.trim_start().starts_with('|');
+                if !self.held {
+                    out.push_str(&self.flush_table());
+                    out.push_str(&self.line);
+                }
+            } else if !self.held {
+                out.push_str(part);
+            }
+            if complete {
+                if self.held {
+                    self.rows.push(std::mem::take(&mut self.line));
+                } else if self.line.trim_start().starts_with("```") {
+                    self.in_code_block = !self.in_code_block;
+                }
+                self.line.clear();
+                self.decided = false;
+                self.held = false;
+            }
+        }
+        out
+    }
+
+    /// End of the text: the text still held back
+    pub fn finish(&mut self) -> String {
+        if self.held {
+            self.rows.push(std::mem::take(&mut self.line));
+        }
+        let mut out = self.flush_table();
+        if !self.decided {
+            out.push_str(&self.line);
+        }
+        *self = Self::default();
+        out
+    }
+
+    fn flush_table(&mut self) -> String {
+        let rows = std::mem::take(&mut self.rows);
+        if rows.is_empty() {
+            return String::new();
+        }
+        render_rows(&rows).unwrap_or_else(|| rows.concat())
+    }
+}
+
+/// Render the Markdown tables in the text as aligned terminal tables
+pub fn render_markdown(text: &str) -> String {
+    let mut tables = TableBuffer::new();
+    let mut out = tables.push(text);
+    out.push_str(&tables.finish());
+    out
+}
+
+/// Render a JSON response as a table: an array of objects, an object holding such an array,
+/// or a single object as a key-value table. Returns `None` for other JSON values.
+pub fn render_json(text: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(text.trim()).ok()?;
+    let items = match &value {
+        Value::Array(items) => items,
+        Value::Object(object) => {
+            let arrays: Vec<&Vec<Value>> = object.values().filter_map(Value::as_array).collect();
+            match arrays[..] {
+                [items] if object.len() == 1 => items,
+                _ => {
+                    let rows = object
+                        .iter()
+                        .map(|(key, value)| vec![key.clone(), cell(value)])
+                        .collect();
+                    let header = vec!["key".to_owned(), "value".to_owned()];
+                    return Some(draw(&header, &[Alignment::Left; 2], rows));
+                }
+            }
+        }
+        _ => return None,
+    };
+    let mut header: Vec<String> = Vec::new();
+    for item in items {
+        for key in item.as_object()?.keys() {
+            if !header.contains(key) {
+                header.push(key.clone());
+            }
+        }
+    }
+    let rows = items
+        .iter()
+        .map(|item| {
+            header
+                .iter()
+                .map(|key| item.get(key).map(cell).unwrap_or_default())
+                .collect()
+        })
+        .collect();
+    if header.is_empty() {
+        return None;
+    }
+    let aligns = vec![Alignment::Left; header.len()];
+    Some(draw(&header, &aligns, rows))
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Render the lines of a Markdown table, or `None` if they aren't a valid table
+fn render_rows(lines: &[String]) -> Option<String> {
+    let rows: Vec<Vec<String>> = lines.iter().map(|line| split_row(line)).collect();
+    let [header, separator, body @ ..] = &rows[..] else {
+        return None;
+    };
+    let aligns = separator
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                None
+            } else if cell.starts_with(':') && cell.ends_with(':') {
+                Some(Alignment::Center)
+            } else if cell.ends_with(':') {
+                Some(Alignment::Right)
+            } else {
+                Some(Alignment::Left)
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if aligns.len() != header.len() {
+        return None;
+    }
+    let body = body
+        .iter()
+        .map(|row| {
+            let mut row = row.clone();
+            row.resize(header.len(), String::new());
+            row
+        })
+        .collect();
+    Some(draw(header, &aligns, body))
+}
+
+/// Cells of a Markdown table row, unescaping `\|`
+fn split_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    let mut cells = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cells.last_mut().unwrap().push('|');
+                chars.next();
+            }
+            '|' => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells.iter().map(|cell| cell.trim().to_owned()).collect()
+}
+
+/// Draw a table with box-drawing characters, wrapping cells to fit the terminal width
+fn draw(header: &[String], aligns: &[Alignment], rows: Vec<Vec<String>>) -> String {
+    let columns = header.len();
+    let mut widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            std::iter::once(header)
+                .chain(rows.iter().map(Vec::as_slice))
+                .map(|row| measure_text_width(&row[i]))
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        })
+        .collect();
+
+    // Narrow the widest columns until the table fits, borders taking 3 columns per cell
+    let available = (console::Term::stdout().size().1 as usize).saturating_sub(3 * columns + 1);
+    while widths.iter().sum::<usize>() > available {
+        let widest = (0..columns).max_by_key(|&i| widths[i]).unwrap();
+        if widths[widest] <= 8 {
+            break;
+        }
+        widths[widest] -= 1;
+    }
+
+    let border = |left: &str, middle: &str, right: &str| {
+        let lines: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        format!("{left}{}{right}\n", lines.join(middle))
+    };
+    let row = |cells: &[String], aligns: &[Alignment]| {
+        let wrapped: Vec<Vec<String>> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| wrap(cell, width))
+            .collect();
+        let height = wrapped.iter().map(Vec::len).max().unwrap_or(1);
+        let mut out = String::new();
+        for line in 0..height {
+            out.push('│');
+            for (i, cell) in wrapped.iter().enumerate() {
+                let text = cell.get(line).map_or("", String::as_str);
+                out.push_str(&format!(" {} │", pad_str(text, widths[i], aligns[i], None)));
+            }
+            out.push('\n');
+        }
+        out
+    };
+
+    let mut out = border("┌", "┬", "┐");
+    out.push_str(&row(header, &vec![Alignment::Left; columns]));
+    out.push_str(&border("├", "┼", "┤"));
+    for cells in &rows {
+        out.push_str(&row(cells, aligns));
+    }
+    out.push_str(&border("└", "┴", "┘"));
+    out
+}
+
+/// Wrap text at word boundaries to lines of at most `width` columns, splitting long words
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    for word in text.split_whitespace() {
+        let line = lines.last_mut().unwrap();
+        let line_width = measure_text_width(line);
+        let word_width = measure_text_width(word);
+        if line_width > 0 && line_width + 1 + word_width <= width {
+            line.push(' ');
+            line.push_str(word);
+            continue;
+        }
+        if line_width > 0 {
+            lines.push(String::new());
+        }
+        for c in word.chars() {
+            let line = lines.last_mut().unwrap();
+            if measure_text_width(line) + measure_text_width(c.encode_utf8(&mut [0; 4])) > width {
+                lines.push(String::new());
+            }
+            lines.last_mut().unwrap().push(c);
+        }
+    }
+    lines
+}
@@ -0,0 +1,69 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::model::Message;
+use crate::sessions::format_time;
+
+/// A Markdown transcript of an interactive session, for `--tee`.
+///
+/// Each exchange is appended as soon as the response is complete, so the transcript survives
+/// the process dying before the session is saved.
+pub struct Tee {
+    file: File,
+}
+
+impl Tee {
+    /// Open the transcript for appending, and start a section for this session
+    pub fn open(path: &str) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open the transcript {path}"))?;
+        let separator = if file.metadata()?.len() > 0 { "\n" } else { "" };
+        writeln!(
+            file,
+            "{separator}# heygpt, {} UTC",
+            format_time(secs(SystemTime::now()))
+        )?;
+        Ok(Self { file })
+    }
+
+    /// Append a prompt sent at `sent`, the names of the files attached to it, and the response
+    pub fn exchange(
+        &mut self,
+        sent: SystemTime,
+        prompt: &str,
+        attachments: &[String],
+        response: &Message,
+    ) -> Result<()> {
+        let mut entry = format!(
+            "\n## user, {} UTC\n\n{}\n",
+            format_time(secs(sent)),
+            prompt.trim_end()
+        );
+        if !attachments.is_empty() {
+            entry.push_str(&format!("\n(attached: {})\n", attachments.join(", ")));
+        }
+        let time = format_time(secs(SystemTime::now()));
+        match &response.meta {
+            Some(meta) => {
+                entry.push_str(&format!("\n## {}, {time} UTC ({meta})\n\n", response.role))
+            }
+            None => entry.push_str(&format!("\n## {}, {time} UTC\n\n", response.role)),
+        }
+        entry.push_str(response.content.trim_end());
+        entry.push('\n');
+        // One write, so that an interrupted session doesn't leave half an exchange
+        self.file
+            .write_all(entry.as_bytes())
+            .context("Failed to write the transcript")
+    }
+}
+
+fn secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_secs()
+}